@@ -5,7 +5,9 @@
 
 use crate::controller::Controller;
 use crate::discovery::Discovery;
-use crate::protocol::decoder::{Config, Response, Scene, Value};
+use crate::protocol::decoder::{Config, Response, Scene, SerialNumber, Value};
+use crate::protocol::encoder::Commands;
+use crate::protocol::monitor::{is_reserved_id, ObservedBus};
 use crate::scenes::scene::LumenCacheScene;
 use crate::zones::device::{BuiltLumenCacheDevice, LumenCacheDevice};
 use as_any::Downcast;
@@ -23,6 +25,8 @@ pub struct LumenCacheAdapter {
     title: String,
     controller: Controller,
     discovery: Discovery,
+    passive: bool,
+    observed: ObservedBus,
     devices: HashMap<u8, Arc<Mutex<Box<dyn Device>>>>,
     scenes: HashMap<u8, Arc<Mutex<Box<dyn Device>>>>,
 }
@@ -33,6 +37,8 @@ impl LumenCacheAdapter {
             id,
             title,
             controller: controller.clone(),
+            passive: config.expert_settings.passive,
+            observed: ObservedBus::default(),
             discovery: Discovery::new(config, controller),
             devices: HashMap::new(),
             scenes: HashMap::new(),
@@ -62,7 +68,45 @@ impl BuiltLumenCacheAdapter {
             Response::Scene(scene) => {
                 self.on_scene_update(scene).await;
             }
-            _ => {}
+            Response::SerialNumber(serial_number) => {
+                self.on_serial_number_update(serial_number).await;
+            }
+            Response::Malformed { raw, reason } => {
+                log::debug!("Ignoring malformed frame {}: {}", raw, reason);
+            }
+            Response::Unknown(parts) => {
+                log::info!("Received frame with unknown layout {{{}}}", parts.join(","));
+            }
+        }
+    }
+
+    /// Applies a command of the controller on a passively monitored bus
+    pub async fn on_command(&mut self, command: Commands) {
+        log::debug!("Observed {:?}", command);
+
+        if let Some(response) = self.observed.on_command(command) {
+            self.on_message(response).await;
+        }
+    }
+
+    pub async fn on_connected(&mut self) {
+        if self.passive {
+            log::info!("Waiting for the traffic of another controller to discover devices");
+            return;
+        }
+
+        if self.devices.is_empty() {
+            self.discovery.start().await;
+            return;
+        }
+
+        for device in self.devices.values() {
+            device
+                .lock()
+                .await
+                .downcast_ref::<BuiltLumenCacheDevice>()
+                .unwrap()
+                .request_initial_values();
         }
     }
 
@@ -84,16 +128,64 @@ impl BuiltLumenCacheAdapter {
         }
     }
 
+    pub async fn on_serial_number_update(&mut self, serial_number: SerialNumber) {
+        let SerialNumber { id, serial_number } = serial_number;
+
+        let matches = match self.devices.get(&id) {
+            Some(device) => device
+                .lock()
+                .await
+                .downcast_ref::<BuiltLumenCacheDevice>()
+                .unwrap()
+                .has_serial_number(&serial_number),
+            None => {
+                log::debug!("No device with the id {} found", id);
+                return;
+            }
+        };
+
+        if !matches {
+            log::warn!(
+                "Id {} is now used by module {}, requesting its config",
+                id,
+                serial_number
+            );
+
+            if let Some(device) = self.devices.remove(&id) {
+                let device_id = device.lock().await.device_handle().device_id.clone();
+
+                if let Err(err) = self.adapter_handle.remove_device(&device_id).await {
+                    log::warn!("Failed to remove device {}: {}", device_id, err);
+                }
+            }
+
+            let mut controller = self.controller.clone();
+
+            tokio::spawn(async move {
+                controller.request_config(id).await;
+            });
+        }
+    }
+
     pub async fn on_config_update(&mut self, config: Config) {
         let id = config.id;
 
-        if id == 0 {
+        if is_reserved_id(id) {
             return;
         };
 
+        if self.passive {
+            self.observed.on_config(&config);
+        }
+
         #[allow(clippy::map_entry)]
         if !self.devices.contains_key(&id) {
-            log::debug!("Creating device {}", id);
+            log::debug!(
+                "Creating device {} ({}, {})",
+                id,
+                config.hardware_type,
+                config.mode
+            );
             let controller = self.controller.clone();
 
             let device = self
@@ -102,6 +194,12 @@ impl BuiltLumenCacheAdapter {
                 .await
                 .unwrap();
 
+            self.devices.insert(id, device.clone());
+
+            if self.passive {
+                return;
+            }
+
             device
                 .lock()
                 .await
@@ -109,13 +207,22 @@ impl BuiltLumenCacheAdapter {
                 .unwrap()
                 .request_initial_values();
 
-            self.devices.insert(id, device);
-
             let mut controller = self.controller.clone();
 
             tokio::spawn(async move {
                 controller.request_scenes(id).await;
             });
+        } else if let Some(device) = self.devices.get(&id) {
+            if let Err(err) = device
+                .lock()
+                .await
+                .downcast_mut::<BuiltLumenCacheDevice>()
+                .unwrap()
+                .set_config(config)
+                .await
+            {
+                log::warn!("Failed to update config of device {}: {}", id, err);
+            }
         }
     }
 
@@ -145,7 +252,10 @@ impl BuiltLumenCacheAdapter {
 #[async_trait]
 impl Adapter for BuiltLumenCacheAdapter {
     async fn on_start_pairing(&mut self, _timeout: Duration) -> Result<(), String> {
-        self.discovery.start().await;
+        if !self.passive {
+            self.discovery.start().await;
+        }
+
         Ok(())
     }
 }
@@ -4,7 +4,11 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/.*
  */
 
+use crate::protocol::encoder::CommandKind;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
@@ -15,9 +19,41 @@ pub struct Config {
     #[serde(default)]
     pub tcp_adapters: Vec<TcpAdapter>,
     #[serde(default)]
+    pub unix_adapters: Vec<UnixAdapter>,
+    #[serde(default)]
+    pub pty_adapters: Vec<PtyAdapter>,
+    #[serde(default)]
+    pub simulator_adapters: Vec<SimulatorAdapter>,
+    #[serde(default)]
+    pub replay_adapters: Vec<ReplayAdapter>,
+    #[serde(default)]
     pub expert_settings: ExpertSettings,
 }
 
+impl Config {
+    pub fn validate(&self) -> Result<()> {
+        for adapter in &self.serial_adapters {
+            adapter
+                .line_settings
+                .validate()
+                .map_err(|err| anyhow!("Invalid serial adapter '{}': {}", adapter.title, err))?;
+        }
+
+        for adapter in &self.tcp_adapters {
+            adapter
+                .line_settings
+                .validate()
+                .map_err(|err| anyhow!("Invalid TCP adapter '{}': {}", adapter.title, err))?;
+        }
+
+        self.expert_settings
+            .validate()
+            .map_err(|err| anyhow!("Invalid expert settings: {}", err))?;
+
+        Ok(())
+    }
+}
+
 #[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct SerialAdapter {
@@ -25,6 +61,70 @@ pub struct SerialAdapter {
     pub id: String,
     pub title: String,
     pub port: String,
+    #[serde(flatten)]
+    pub line_settings: LineSettings,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LineSettings {
+    pub baud_rate: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+}
+
+impl Default for LineSettings {
+    fn default() -> Self {
+        LineSettings {
+            baud_rate: 38400,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+        }
+    }
+}
+
+impl LineSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.baud_rate == 0 {
+            return Err(anyhow!("Baud rate must be greater than 0"));
+        }
+
+        if !(5..=8).contains(&self.data_bits) {
+            return Err(anyhow!(
+                "Expected 5 to 8 data bits but got {}",
+                self.data_bits
+            ));
+        }
+
+        if !(1..=2).contains(&self.stop_bits) {
+            return Err(anyhow!(
+                "Expected 1 or 2 stop bits but got {}",
+                self.stop_bits
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FlowControl {
+    None,
+    Software,
+    Hardware,
 }
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
@@ -35,14 +135,93 @@ pub struct TcpAdapter {
     pub title: String,
     pub host: String,
     pub port: u16,
+    #[serde(default)]
+    pub protocol: TcpProtocol,
+    #[serde(flatten)]
+    pub line_settings: LineSettings,
+}
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum TcpProtocol {
+    #[default]
+    Raw,
+    Rfc2217,
 }
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
 #[serde(rename_all = "camelCase")]
+pub struct UnixAdapter {
+    #[serde(default = "uuid")]
+    pub id: String,
+    pub title: String,
+    pub path: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PtyAdapter {
+    #[serde(default = "uuid")]
+    pub id: String,
+    pub title: String,
+    pub path: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayAdapter {
+    #[serde(default = "uuid")]
+    pub id: String,
+    pub title: String,
+    pub path: String,
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatorAdapter {
+    #[serde(default = "uuid")]
+    pub id: String,
+    pub title: String,
+    #[serde(default = "default_modules")]
+    pub modules: usize,
+    #[serde(default)]
+    pub faults: Faults,
+}
+
+/// Probabilities of the faults injected into each reply frame of a simulated bus
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Faults {
+    pub seed: u64,
+    pub drop: f64,
+    pub delay: f64,
+    pub max_delay_ms: u64,
+    pub duplicate: f64,
+    pub corrupt: f64,
+    pub reorder: f64,
+    pub garbage: f64,
+}
+
+fn default_modules() -> usize {
+    3
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase", default)]
 pub struct ExpertSettings {
     pub max_id: u8,
     pub tx_delay_ms: u64,
+    /// Adjusts the delay between `min_tx_delay_ms` and `max_tx_delay_ms` to the bus
+    pub adaptive_tx_delay: bool,
+    pub min_tx_delay_ms: u64,
+    pub max_tx_delay_ms: u64,
     pub response_timeout_ms: u64,
+    pub reconnect_delay_ms: u64,
+    pub max_reconnect_delay_ms: u64,
+    pub capture_directory: Option<String>,
+    pub passive: bool,
+    pub retries: RetryPolicies,
+    pub command_timeouts_ms: CommandTimeouts,
 }
 
 impl Default for ExpertSettings {
@@ -50,7 +229,221 @@ impl Default for ExpertSettings {
         ExpertSettings {
             max_id: 240,
             tx_delay_ms: 200,
+            adaptive_tx_delay: false,
+            min_tx_delay_ms: 20,
+            max_tx_delay_ms: 2000,
             response_timeout_ms: 500,
+            reconnect_delay_ms: 1000,
+            max_reconnect_delay_ms: 60000,
+            capture_directory: None,
+            passive: false,
+            retries: RetryPolicies::default(),
+            command_timeouts_ms: CommandTimeouts::default(),
+        }
+    }
+}
+
+impl ExpertSettings {
+    pub fn validate(&self) -> Result<()> {
+        if self.min_tx_delay_ms > self.max_tx_delay_ms {
+            return Err(anyhow!(
+                "Minimum tx delay of {} ms is greater than the maximum of {} ms",
+                self.min_tx_delay_ms,
+                self.max_tx_delay_ms
+            ));
+        }
+
+        if self.adaptive_tx_delay
+            && !(self.min_tx_delay_ms..=self.max_tx_delay_ms).contains(&self.tx_delay_ms)
+        {
+            return Err(anyhow!(
+                "Tx delay of {} ms must be between the minimum of {} ms and the maximum of {} ms",
+                self.tx_delay_ms,
+                self.min_tx_delay_ms,
+                self.max_tx_delay_ms
+            ));
+        }
+
+        self.command_timeouts_ms.validate()?;
+        self.retries.validate()
+    }
+}
+
+const MIN_TIMEOUT_MS: u64 = 10;
+const MAX_TIMEOUT_MS: u64 = 60000;
+
+/// Time in milliseconds to wait for the response to each kind of command.
+///
+/// Commands without a configured timeout use their default, a `null` timeout counts as not
+/// configured.
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq, Default)]
+#[serde(transparent)]
+pub struct CommandTimeouts {
+    timeouts_ms: HashMap<CommandKind, Option<u64>>,
+}
+
+impl CommandTimeouts {
+    /// Returns the timeout of `kind`, or `None` to use `response_timeout_ms`
+    pub fn get(&self, kind: CommandKind) -> Option<u64> {
+        self.timeouts_ms
+            .get(&kind)
+            .copied()
+            .flatten()
+            .or_else(|| default_timeout_ms(kind))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (kind, timeout_ms) in &self.timeouts_ms {
+            if let Some(timeout_ms) = timeout_ms {
+                if !(MIN_TIMEOUT_MS..=MAX_TIMEOUT_MS).contains(timeout_ms) {
+                    return Err(anyhow!(
+                        "Timeout of {:?} must be between {} and {} ms, not {} ms",
+                        kind,
+                        MIN_TIMEOUT_MS,
+                        MAX_TIMEOUT_MS,
+                        timeout_ms
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Default timeouts of the commands which take longer than others to be answered
+fn default_timeout_ms(kind: CommandKind) -> Option<u64> {
+    match kind {
+        CommandKind::ActivateScene | CommandKind::DeactivateScene => Some(3000),
+        CommandKind::Hail => Some(5000),
+        _ => None,
+    }
+}
+
+/// How a command is re-sent after its response timed out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of times the command is sent, including the first one
+    pub max_attempts: u32,
+    pub backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Whether sending the command twice has the same effect as sending it once
+    pub safe_to_retry: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            backoff_ms: 100,
+            max_backoff_ms: 1000,
+            safe_to_retry: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay between attempt `attempt` and the next one, which doubles with
+    /// every attempt up to `max_backoff_ms`
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1_u64 << attempt.saturating_sub(1).min(32);
+        let backoff_ms = self.backoff_ms.saturating_mul(factor);
+        Duration::from_millis(backoff_ms.min(self.max_backoff_ms))
+    }
+
+    /// Returns the number of attempts the policy allows
+    pub fn attempts(&self) -> u32 {
+        if self.safe_to_retry {
+            self.max_attempts.max(1)
+        } else {
+            1
+        }
+    }
+}
+
+/// Default retry policy of each kind of command
+fn default_retry_policy(kind: CommandKind) -> RetryPolicy {
+    match kind {
+        // A second deactivation drops the resume level restored by the first one
+        CommandKind::DeactivateScene => RetryPolicy {
+            safe_to_retry: false,
+            ..RetryPolicy::default()
+        },
+        // Discovery probes every id, most of which are not in use, and hails again on its own.
+        // Background reads are repeated by the next poll anyway, so they don't hold up the queue.
+        CommandKind::GetConfig
+        | CommandKind::Hail
+        | CommandKind::GetValue
+        | CommandKind::GetScenes => RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        },
+        _ => RetryPolicy::default(),
+    }
+}
+
+/// Retry policy of each kind of command.
+///
+/// The configured fields of a command replace the fields of its default policy, the others
+/// keep their defaults.
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq, Default)]
+#[serde(transparent)]
+pub struct RetryPolicies {
+    overrides: HashMap<CommandKind, Option<RetryOverrides>>,
+}
+
+impl RetryPolicies {
+    pub fn get(&self, kind: CommandKind) -> RetryPolicy {
+        let policy = default_retry_policy(kind);
+
+        match self.overrides.get(&kind) {
+            Some(Some(overrides)) => overrides.apply(policy),
+            _ => policy,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for kind in self.overrides.keys() {
+            let policy = self.get(*kind);
+
+            if policy.max_attempts == 0 {
+                return Err(anyhow!(
+                    "Retry policy of {:?} needs at least 1 attempt",
+                    kind
+                ));
+            }
+
+            if policy.backoff_ms > policy.max_backoff_ms {
+                return Err(anyhow!(
+                    "Backoff of {:?} of {} ms is greater than the maximum of {} ms",
+                    kind,
+                    policy.backoff_ms,
+                    policy.max_backoff_ms
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Configured fields of a `RetryPolicy`
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+struct RetryOverrides {
+    max_attempts: Option<u32>,
+    backoff_ms: Option<u64>,
+    max_backoff_ms: Option<u64>,
+    safe_to_retry: Option<bool>,
+}
+
+impl RetryOverrides {
+    fn apply(&self, policy: RetryPolicy) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(policy.max_attempts),
+            backoff_ms: self.backoff_ms.unwrap_or(policy.backoff_ms),
+            max_backoff_ms: self.max_backoff_ms.unwrap_or(policy.max_backoff_ms),
+            safe_to_retry: self.safe_to_retry.unwrap_or(policy.safe_to_retry),
         }
     }
 }
@@ -58,3 +451,276 @@ impl Default for ExpertSettings {
 fn uuid() -> String {
     Uuid::new_v4().to_string()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_default_line_settings() {
+        let adapter: SerialAdapter = serde_json::from_value(json!({
+            "title": "Serial",
+            "port": "/dev/ttyAMA0"
+        }))
+        .unwrap();
+        assert_eq!(adapter.line_settings, LineSettings::default());
+    }
+
+    #[test]
+    fn test_line_settings() {
+        let adapter: SerialAdapter = serde_json::from_value(json!({
+            "title": "Serial",
+            "port": "/dev/ttyAMA0",
+            "baudRate": 9600,
+            "dataBits": 7,
+            "parity": "even",
+            "stopBits": 2,
+            "flowControl": "hardware"
+        }))
+        .unwrap();
+        assert_eq!(
+            adapter.line_settings,
+            LineSettings {
+                baud_rate: 9600,
+                data_bits: 7,
+                parity: Parity::Even,
+                stop_bits: 2,
+                flow_control: FlowControl::Hardware,
+            }
+        );
+        assert!(adapter.line_settings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_line_settings() {
+        let config: Config = serde_json::from_value(json!({
+            "serialAdapters": [{
+                "title": "Serial",
+                "port": "/dev/ttyAMA0",
+                "dataBits": 9
+            }]
+        }))
+        .unwrap();
+        assert!(config.validate().is_err());
+
+        assert!(serde_json::from_value::<SerialAdapter>(json!({
+            "title": "Serial",
+            "port": "/dev/ttyAMA0",
+            "parity": "mark"
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn test_path_adapters() {
+        let config: Config = serde_json::from_value(json!({
+            "unixAdapters": [{
+                "title": "Unix",
+                "path": "/run/ser2net/lumencache.sock"
+            }],
+            "ptyAdapters": [{
+                "title": "PTY",
+                "path": "/dev/pts/3"
+            }]
+        }))
+        .unwrap();
+        assert!(config.serial_adapters.is_empty());
+        assert_eq!(config.unix_adapters[0].path, "/run/ser2net/lumencache.sock");
+        assert_eq!(config.pty_adapters[0].path, "/dev/pts/3");
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_tcp_protocol() {
+        let adapter: TcpAdapter = serde_json::from_value(json!({
+            "title": "TCP",
+            "host": "gateway",
+            "port": 2217
+        }))
+        .unwrap();
+        assert_eq!(adapter.protocol, TcpProtocol::Raw);
+
+        let adapter: TcpAdapter = serde_json::from_value(json!({
+            "title": "TCP",
+            "host": "gateway",
+            "port": 2217,
+            "protocol": "rfc2217",
+            "baudRate": 9600
+        }))
+        .unwrap();
+        assert_eq!(adapter.protocol, TcpProtocol::Rfc2217);
+        assert_eq!(adapter.line_settings.baud_rate, 9600);
+    }
+
+    #[test]
+    fn test_retry_policies() {
+        let config: Config = serde_json::from_value(json!({
+            "expertSettings": {
+                "retries": {
+                    "setValue": {
+                        "maxAttempts": 5,
+                        "backoffMs": 50
+                    },
+                    "getValue": {
+                        "safeToRetry": false
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let retries = config.expert_settings.retries;
+
+        let set_value = retries.get(CommandKind::SetValue);
+        assert_eq!(set_value.attempts(), 5);
+        assert_eq!(set_value.backoff_ms, 50);
+        assert_eq!(set_value.max_backoff_ms, 1000);
+        assert_eq!(retries.get(CommandKind::GetValue).attempts(), 1);
+        assert_eq!(retries.get(CommandKind::GetScenes).attempts(), 1);
+        assert_eq!(retries.get(CommandKind::SetScene).attempts(), 3);
+        assert_eq!(retries.get(CommandKind::GetConfig).attempts(), 1);
+        assert_eq!(retries.get(CommandKind::DeactivateScene).attempts(), 1);
+        assert_eq!(retries.get(CommandKind::Hail).attempts(), 1);
+
+        assert_eq!(set_value.backoff(1), Duration::from_millis(50));
+        assert_eq!(set_value.backoff(2), Duration::from_millis(100));
+        assert_eq!(set_value.backoff(100), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn test_invalid_retry_policies() {
+        for retries in [
+            json!({ "setValue": { "maxAttempts": 0 } }),
+            json!({ "setValue": { "backoffMs": 500, "maxBackoffMs": 100 } }),
+            // The backoff exceeds the default maximum of 1000 ms
+            json!({ "getValue": { "backoffMs": 2000 } }),
+        ] {
+            let config: Config = serde_json::from_value(json!({
+                "expertSettings": {
+                    "retries": retries
+                }
+            }))
+            .unwrap();
+            assert!(config.validate().is_err(), "{} was accepted", retries);
+        }
+    }
+
+    #[test]
+    fn test_partial_retry_policies() {
+        let config: Config = serde_json::from_value(json!({
+            "expertSettings": {
+                "retries": {
+                    "deactivateScene": {
+                        "maxAttempts": 2
+                    },
+                    "getConfig": {
+                        "backoffMs": 50
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let retries = config.expert_settings.retries;
+
+        // The other fields keep the defaults of the command, not the generic ones
+        let deactivate_scene = retries.get(CommandKind::DeactivateScene);
+        assert!(!deactivate_scene.safe_to_retry);
+        assert_eq!(deactivate_scene.attempts(), 1);
+
+        let get_config = retries.get(CommandKind::GetConfig);
+        assert_eq!(get_config.backoff_ms, 50);
+        assert_eq!(get_config.attempts(), 1);
+    }
+
+    #[test]
+    fn test_command_timeouts() {
+        let config: Config = serde_json::from_value(json!({
+            "expertSettings": {
+                "commandTimeoutsMs": {
+                    "activateScene": 8000,
+                    "getValue": 100
+                }
+            }
+        }))
+        .unwrap();
+        let timeouts = &config.expert_settings.command_timeouts_ms;
+
+        assert_eq!(timeouts.get(CommandKind::ActivateScene), Some(8000));
+        assert_eq!(timeouts.get(CommandKind::DeactivateScene), Some(3000));
+        assert_eq!(timeouts.get(CommandKind::Hail), Some(5000));
+        assert_eq!(timeouts.get(CommandKind::GetValue), Some(100));
+        assert_eq!(timeouts.get(CommandKind::SetValue), None);
+        assert!(config.validate().is_ok());
+
+        let config: Config = serde_json::from_value(json!({
+            "expertSettings": {
+                "commandTimeoutsMs": {
+                    "hail": 0
+                }
+            }
+        }))
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_null_command_timeout() {
+        let config: Config = serde_json::from_value(json!({
+            "expertSettings": {
+                "commandTimeoutsMs": {
+                    "hail": null
+                },
+                "retries": {
+                    "deactivateScene": null
+                }
+            }
+        }))
+        .unwrap();
+        let expert_settings = &config.expert_settings;
+
+        assert_eq!(
+            expert_settings.command_timeouts_ms.get(CommandKind::Hail),
+            Some(5000)
+        );
+        assert_eq!(
+            expert_settings
+                .retries
+                .get(CommandKind::DeactivateScene)
+                .attempts(),
+            1
+        );
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_tx_delay_bounds() {
+        let config: Config = serde_json::from_value(json!({
+            "expertSettings": {
+                "adaptiveTxDelay": true,
+                "minTxDelayMs": 500,
+                "maxTxDelayMs": 100
+            }
+        }))
+        .unwrap();
+        assert!(config.expert_settings.adaptive_tx_delay);
+        assert!(config.validate().is_err());
+
+        // The initial delay is only bounded when it is adjusted
+        let config: Config = serde_json::from_value(json!({
+            "expertSettings": {
+                "txDelayMs": 5000
+            }
+        }))
+        .unwrap();
+        assert!(config.validate().is_ok());
+
+        let config: Config = serde_json::from_value(json!({
+            "expertSettings": {
+                "adaptiveTxDelay": true,
+                "txDelayMs": 5000
+            }
+        }))
+        .unwrap();
+        assert!(config.validate().is_err());
+    }
+}
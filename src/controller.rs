@@ -3,87 +3,215 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+use crate::config::ExpertSettings;
 use crate::protocol::decoder::{Config, Response, Scene, Value};
 use crate::protocol::encoder::{
-    ActivateSceneCommand, AssignIdCommand, ClearSceneCommand, ClearScenesCommand, Commands,
-    DeactivateSceneCommand, GetConfigCommand, GetScenesCommand, GetValueCommand, SetSceneCommand,
-    SetValueCommand,
+    ActivateSceneCommand, AssignIdCommand, ClearSceneCommand, ClearScenesCommand, CommandKind,
+    Commands, ConfigField, DeactivateSceneCommand, GetConfigCommand, GetScenesCommand,
+    GetValueCommand, SetConfigCommand, SetSceneCommand, SetValueCommand,
 };
+use crate::queue::{QueuedRequest, RequestQueue, RequestSender};
 use crate::request::{Request, RequestResponse, ResponseMatcher};
-use crate::throttle::Throttle;
+use crate::throttle::{Throttle, ThrottleStats};
 use crate::transport::Transport;
+use anyhow::{Error, Result};
 use futures::FutureExt;
 use std::sync::Arc;
 use tokio::sync::oneshot::Receiver;
 use tokio::{
     select,
-    sync::{mpsc, oneshot, Mutex},
-    time::{sleep, Duration},
+    sync::{oneshot, watch, Mutex, Notify},
+    time::{sleep, Duration, Instant},
 };
 
-fn command_timeout(command: &Commands, default_ms: u64) -> Duration {
-    match command {
-        Commands::Hail => Duration::from_millis(5000),
-        Commands::ActivateScene(_) => Duration::from_millis(3000),
-        Commands::DeactivateScene(_) => Duration::from_millis(3000),
-        _ => Duration::from_millis(default_ms),
+fn create_throttle(expert_settings: &ExpertSettings) -> Throttle {
+    let tx_delay = Duration::from_millis(expert_settings.tx_delay_ms);
+
+    if expert_settings.adaptive_tx_delay {
+        Throttle::adaptive(
+            tx_delay,
+            Duration::from_millis(expert_settings.min_tx_delay_ms),
+            Duration::from_millis(expert_settings.max_tx_delay_ms),
+        )
+    } else {
+        Throttle::new(tx_delay)
     }
 }
 
+/// Publishes the stats of `throttle` and logs them when the gap changed.
+///
+/// Only a grown gap or one at its limit is logged at info, the small steps of shrinking it
+/// after every response are logged at debug.
+fn publish_stats(throttle: &Throttle, stats_tx: &watch::Sender<ThrottleStats>) {
+    let stats = throttle.stats();
+    let previous = stats_tx.borrow().gap;
+
+    if stats.gap != previous {
+        let level = if stats.gap > previous || throttle.at_limit() {
+            log::Level::Info
+        } else {
+            log::Level::Debug
+        };
+
+        log::log!(
+            level,
+            "Gap between commands is now {:?} (smoothed RTT {:?}, timeout rate {:.2})",
+            stats.gap,
+            stats.smoothed_rtt,
+            stats.timeout_rate
+        );
+    }
+
+    let _ = stats_tx.send(stats);
+}
+
+async fn send(transport: &Arc<Mutex<dyn Transport>>, command: &Commands) -> Result<(), Error> {
+    transport.lock().await.send(command.clone()).await?;
+    log::debug!("Sent command {:?}", command);
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct Controller {
-    tx: mpsc::Sender<Request>,
+    tx: RequestSender,
     response_matcher: Arc<Mutex<ResponseMatcher>>,
+    throttle_stats: watch::Receiver<ThrottleStats>,
+    disconnected: Arc<Notify>,
+    passive: bool,
 }
 
 impl Controller {
     pub fn start(config: crate::Config, transport: Arc<Mutex<dyn Transport>>) -> Self {
-        let (tx, mut rx) = mpsc::channel::<Request>(100);
+        let (tx, mut queue) = RequestQueue::new();
         let request = Arc::new(Mutex::new(ResponseMatcher::new()));
         let response_matcher = request.clone();
-        let tx_delay_ms = config.expert_settings.tx_delay_ms;
+        let mut throttle = create_throttle(&config.expert_settings);
         let response_timeout_ms = config.expert_settings.response_timeout_ms;
+        let passive = config.expert_settings.passive;
+        let retries = config.expert_settings.retries;
+        let command_timeouts = config.expert_settings.command_timeouts_ms;
+        let (stats_tx, throttle_stats) = watch::channel(throttle.stats());
+        let disconnected = Arc::new(Notify::new());
+        let on_disconnected = disconnected.clone();
 
         tokio::spawn(async move {
-            let mut throttle = Throttle::new(Duration::from_millis(tx_delay_ms));
-
-            while let Some(request) = rx.recv().await {
-                throttle.throttle().await;
+            while let Some(QueuedRequest { request, attempt }) = queue.next().await {
+                if passive {
+                    log::debug!(
+                        "Dropping {:?} on a passively monitored bus",
+                        request.command()
+                    );
+                    request.timeout();
+                    continue;
+                }
 
                 let command = request.command();
+                throttle.throttle().await;
 
-                match transport.lock().await.send(command.clone()).await {
-                    Ok(_) => {
-                        log::debug!("Sent command {:?}", command)
-                    }
-                    Err(err) => {
-                        log::debug!("Failed to send command {:?}: {}", command, err)
-                    }
-                }
-
+                // Wait for the response before sending, so that a fast reply is not missed
+                let read_back = request.read_back();
                 let response_future = response_matcher
                     .lock()
                     .await
-                    .wait_for_response_to(request)
+                    .wait_for_response_to(request, attempt)
                     .fuse();
+                let disconnected_future = on_disconnected.notified().fuse();
 
-                let timeout_future = sleep(command_timeout(&command, response_timeout_ms)).fuse();
+                let mut result = send(&transport, &command).await;
+
+                if let (Ok(()), Some(read_back)) = (&result, &read_back) {
+                    throttle.reset_start();
+                    throttle.throttle().await;
+                    result = send(&transport, read_back).await;
+                }
+
+                let sent = Instant::now();
+
+                if let Err(err) = result {
+                    log::debug!("Failed to send command {:?}: {}", command, err);
+
+                    if let Some(request) = response_matcher.lock().await.take_request() {
+                        request.timeout();
+                    }
+                    continue;
+                }
+
+                let timeout_ms = command_timeouts
+                    .get(command.kind())
+                    .unwrap_or(response_timeout_ms);
+                let timeout_future = sleep(Duration::from_millis(timeout_ms)).fuse();
 
                 select! {
                     _response = response_future => {
-                        throttle.reset_start();
+                        throttle.on_response(sent.elapsed());
+                        publish_stats(&throttle, &stats_tx);
+                        continue;
                     },
-                    () = timeout_future => {response_matcher.lock().await.timeout();}
+                    () = timeout_future => {},
+                    () = disconnected_future => {
+                        log::debug!("Lost the connection while waiting for {:?}", command);
+
+                        if let Some(request) = response_matcher.lock().await.take_request() {
+                            request.timeout();
+                        }
+                        continue;
+                    }
+                };
+
+                let request = match response_matcher.lock().await.take_request() {
+                    Some(request) => request,
+                    None => continue,
                 };
+
+                // Discovery probes ids that are mostly unused, so their silence says nothing
+                // about the load of the bus
+                if !matches!(command.kind(), CommandKind::GetConfig | CommandKind::Hail) {
+                    throttle.on_timeout();
+                    publish_stats(&throttle, &stats_tx);
+                }
+
+                let policy = retries.get(command.kind());
+
+                if attempt >= policy.attempts() {
+                    request.timeout();
+                    continue;
+                }
+
+                let delay = policy.backoff(attempt);
+                log::debug!(
+                    "No response to {:?}, sending attempt {} in {:?}",
+                    command,
+                    attempt + 1,
+                    delay
+                );
+                queue.retry(request, attempt + 1, Instant::now() + delay);
             }
         });
 
         Controller {
             tx,
             response_matcher: request,
+            throttle_stats,
+            disconnected,
+            passive,
         }
     }
 
+    /// Whether the bus is only monitored, so nothing can be changed through it
+    pub fn is_passive(&self) -> bool {
+        self.passive
+    }
+
+    /// Fails the request waiting for a response on the lost connection
+    pub fn on_disconnected(&self) {
+        self.disconnected.notify_waiters();
+    }
+
+    /// Returns the current gap between commands and the measurements it is based on
+    pub fn throttle_stats(&self) -> ThrottleStats {
+        *self.throttle_stats.borrow()
+    }
+
     pub async fn check_response(&self, response: &Response) {
         self.response_matcher
             .lock()
@@ -95,7 +223,11 @@ impl Controller {
     pub async fn set_value(&mut self, id: u8, value: u8) -> Receiver<RequestResponse<Value>> {
         let command = SetValueCommand { id, value };
         let (tx, rx) = oneshot::channel();
-        let request = Request::SetValue { command, tx };
+        let request = Request::SetValue {
+            command,
+            tx,
+            superseded: Vec::new(),
+        };
 
         self.enqueue_and_wait(request, rx).await
     }
@@ -194,6 +326,19 @@ impl Controller {
         self.enqueue_and_wait(request, rx).await
     }
 
+    pub async fn set_config(
+        &mut self,
+        id: u8,
+        field: ConfigField,
+        value: u8,
+    ) -> Receiver<RequestResponse<Config>> {
+        let command = SetConfigCommand { id, field, value };
+        let (tx, rx) = oneshot::channel();
+        let request = Request::SetConfig { command, tx };
+
+        self.enqueue_and_wait(request, rx).await
+    }
+
     async fn enqueue_and_wait<T>(
         &mut self,
         request: Request,
@@ -209,3 +354,486 @@ impl Controller {
         rx
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codec::LumenCacheCodec;
+    use crate::transport::mock::{MockHandle, MockTransport, Reply};
+    use bytes::BytesMut;
+    use serde_json::json;
+
+    fn start(script: Vec<Reply>) -> (Controller, MockHandle) {
+        start_with(json!({}), script)
+    }
+
+    fn with_poll_retries() -> serde_json::Value {
+        json!({ "retries": { "getValue": { "maxAttempts": 3 } } })
+    }
+
+    fn start_with(
+        expert_settings: serde_json::Value,
+        script: Vec<Reply>,
+    ) -> (Controller, MockHandle) {
+        let config: crate::Config =
+            serde_json::from_value(json!({ "expertSettings": expert_settings })).unwrap();
+        let (transport, handle, mut responses) = MockTransport::start(script);
+        let controller = Controller::start(config, transport);
+        let receiver = controller.clone();
+
+        tokio::spawn(async move {
+            while let Some(response) = responses.recv().await {
+                receiver.check_response(&response).await;
+            }
+        });
+
+        (controller, handle)
+    }
+
+    fn value(id: u8, value: u8) -> Response {
+        Response::Value(Value { id, value })
+    }
+
+    fn scene(id: u8, scene: u8) -> Response {
+        Response::Scene(Scene {
+            id,
+            scene,
+            level: -1,
+            duration: -1,
+        })
+    }
+
+    fn scenes(id: u8) -> Vec<Response> {
+        (1..=64).map(|nr| scene(id, nr)).collect()
+    }
+
+    fn unwrap<T>(response: RequestResponse<T>) -> T {
+        match response {
+            RequestResponse::Response { value, attempts: 1 } => value,
+            RequestResponse::Response { attempts, .. } => {
+                panic!("Unexpected {} attempts", attempts)
+            }
+            RequestResponse::Timeout => panic!("Unexpected timeout"),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_set_value() {
+        let (mut controller, handle) = start(vec![Reply::after(20, vec![value(5, 42)])]);
+
+        let response = unwrap(controller.set_value(5, 42).await.await.unwrap());
+        assert_eq!(response.id, 5);
+        assert_eq!(response.value, 42);
+        assert_eq!(
+            handle.sent(),
+            vec![Commands::SetValue(SetValueCommand { id: 5, value: 42 })]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_set_config_read_back() {
+        let config = LumenCacheCodec::parse(&mut BytesMut::from(
+            &b"{5,7,3,123456.12,ABC,2,0,2,0,255,255,0,0,0,0}"[..],
+        ))
+        .unwrap();
+        // The module only acknowledges the write, the read back reports the new mode
+        let (mut controller, handle) =
+            start(vec![Reply::silence(), Reply::after(20, vec![config])]);
+
+        let response = unwrap(
+            controller
+                .set_config(5, ConfigField::Mode, 2)
+                .await
+                .await
+                .unwrap(),
+        );
+        assert_eq!(u8::from(response.mode), 2);
+        assert_eq!(
+            handle.sent(),
+            vec![
+                Commands::SetConfig(SetConfigCommand {
+                    id: 5,
+                    field: ConfigField::Mode,
+                    value: 2
+                }),
+                Commands::GetConfig(GetConfigCommand { id: 5 }),
+            ]
+        );
+
+        let sent_at = handle.sent_at();
+        assert_eq!(sent_at[1] - sent_at[0], Duration::from_millis(200));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_timeout() {
+        let (mut controller, handle) = start(vec![Reply::silence(), Reply::silence()]);
+
+        let start = Instant::now();
+        let response = controller.request_current_value(5).await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+
+        let start = Instant::now();
+        let response = controller.hail().await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+        assert_eq!(start.elapsed(), Duration::from_millis(5000));
+
+        assert_eq!(handle.sent().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_command_timeouts() {
+        let (mut controller, _handle) = start_with(
+            json!({ "commandTimeoutsMs": { "deactivateScene": 8000, "setValue": 50 } }),
+            vec![],
+        );
+
+        let start = Instant::now();
+        let response = controller.deactivate_scene(3).await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+        assert_eq!(start.elapsed(), Duration::from_millis(8000));
+
+        // Three attempts with a backoff of 100 ms and 200 ms in between
+        let start = Instant::now();
+        let response = controller.set_value(5, 42).await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+        assert_eq!(start.elapsed(), Duration::from_millis(450));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_send_failure() {
+        let (mut controller, _handle) = start(vec![Reply::Fail]);
+
+        let start = Instant::now();
+        let response = controller.request_current_value(5).await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+        assert_eq!(start.elapsed(), Duration::from_millis(0));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_disconnected() {
+        let (mut controller, handle) = start(vec![Reply::silence()]);
+
+        let start = Instant::now();
+        let receiver = controller.request_current_value(5).await;
+        sleep(Duration::from_millis(100)).await;
+        controller.on_disconnected();
+
+        // The request fails at once instead of being retried on the lost connection
+        assert!(matches!(receiver.await.unwrap(), RequestResponse::Timeout));
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+        assert_eq!(handle.sent().len(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unsolicited_values() {
+        let (mut controller, handle) = start(vec![Reply::after(
+            20,
+            vec![value(6, 1), value(7, 2), value(5, 42)],
+        )]);
+
+        let receiver = controller.request_current_value(5).await;
+        handle.inject(value(8, 3));
+
+        let response = unwrap(receiver.await.unwrap());
+        assert_eq!(response.id, 5);
+        assert_eq!(response.value, 42);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_late_reply() {
+        let (mut controller, _handle) = start(vec![
+            Reply::after(600, vec![value(5, 1)]),
+            Reply::after(200, vec![value(6, 2)]),
+        ]);
+
+        let first = controller.request_current_value(5).await;
+        let second = controller.request_current_value(6).await;
+
+        assert!(matches!(first.await.unwrap(), RequestResponse::Timeout));
+
+        // The late reply to the first request arrives while waiting for the second one
+        let response = unwrap(second.await.unwrap());
+        assert_eq!(response.id, 6);
+        assert_eq!(response.value, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttle() {
+        let (mut controller, handle) = start(vec![
+            Reply::after(20, vec![value(5, 1)]),
+            Reply::after(20, vec![value(6, 2)]),
+        ]);
+
+        let first = controller.set_value(5, 1).await;
+        let second = controller.set_value(6, 2).await;
+        unwrap(first.await.unwrap());
+        unwrap(second.await.unwrap());
+
+        let sent_at = handle.sent_at();
+        assert_eq!(sent_at[1] - sent_at[0], Duration::from_millis(220));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_adaptive_throttle() {
+        let (mut controller, handle) = start_with(
+            json!({ "adaptiveTxDelay": true }),
+            vec![
+                Reply::after(20, vec![value(5, 1)]),
+                Reply::silence(),
+                Reply::after(20, vec![value(6, 2)]),
+            ],
+        );
+        assert_eq!(controller.throttle_stats().gap, Duration::from_millis(200));
+
+        unwrap(controller.set_value(5, 1).await.await.unwrap());
+        assert_eq!(controller.throttle_stats().gap, Duration::from_millis(190));
+
+        // The retry of the timed out request waits twice as long
+        let response = controller.set_value(6, 2).await.await.unwrap();
+        assert!(matches!(
+            response,
+            RequestResponse::Response { attempts: 2, .. }
+        ));
+
+        let sent_at = handle.sent_at();
+        assert_eq!(sent_at[1] - sent_at[0], Duration::from_millis(210));
+        assert_eq!(sent_at[2] - sent_at[1], Duration::from_millis(600));
+
+        let stats = controller.throttle_stats();
+        assert_eq!(stats.gap, Duration::from_millis(380));
+        assert_eq!(stats.smoothed_rtt, Some(Duration::from_millis(20)));
+        assert!(stats.timeout_rate > 0.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_probe_timeouts_keep_gap() {
+        let (mut controller, handle) = start_with(json!({ "adaptiveTxDelay": true }), vec![]);
+
+        for id in 1..=3 {
+            let response = controller.request_config(id).await.await.unwrap();
+            assert!(matches!(response, RequestResponse::Timeout));
+        }
+        let response = controller.hail().await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+
+        assert_eq!(handle.sent().len(), 4);
+        assert_eq!(controller.throttle_stats().gap, Duration::from_millis(200));
+        assert_eq!(controller.throttle_stats().timeout_rate, 0.0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_scenes() {
+        let mut responses = scenes(5);
+        responses.insert(10, value(5, 42));
+        responses.insert(20, scene(6, 1));
+        let (mut controller, handle) = start(vec![Reply::after(20, responses)]);
+
+        let response = unwrap(controller.request_scenes(5).await.await.unwrap());
+        assert_eq!(response.len(), 64);
+        assert!(response.iter().all(|scene| scene.id == 5));
+        assert_eq!(
+            response.iter().map(|scene| scene.scene).collect::<Vec<_>>(),
+            (1..=64).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            handle.sent(),
+            vec![Commands::GetScenes(GetScenesCommand { id: 5 })]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_clear_scenes() {
+        let (mut controller, handle) = start(vec![Reply::after(20, scenes(5))]);
+
+        let response = unwrap(controller.clear_scenes(5).await.await.unwrap());
+        assert_eq!(response.len(), 64);
+        assert_eq!(
+            handle.sent(),
+            vec![Commands::ClearScenes(ClearScenesCommand { id: 5 })]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_incomplete_scenes() {
+        let (mut controller, _handle) = start(vec![
+            Reply::after(20, scenes(5).into_iter().take(10).collect()),
+            Reply::after(20, scenes(5)),
+        ]);
+
+        let response = controller.request_scenes(5).await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+
+        // The scenes of the timed out request must not leak into the next one
+        let response = unwrap(controller.request_scenes(5).await.await.unwrap());
+        assert_eq!(response.len(), 64);
+        assert_eq!(response[0].scene, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interactive_jumps_discovery_scan() {
+        let (mut controller, handle) = start(vec![
+            Reply::silence(),
+            Reply::silence(),
+            Reply::after(20, vec![value(5, 42)]),
+        ]);
+
+        let mut scan = Vec::new();
+        for id in 1..=20 {
+            scan.push(controller.request_config(id).await);
+        }
+
+        sleep(Duration::from_millis(750)).await;
+
+        let start = Instant::now();
+        let response = unwrap(controller.set_value(5, 42).await.await.unwrap());
+        assert_eq!(response.value, 42);
+        assert_eq!(start.elapsed(), Duration::from_millis(270));
+
+        let sent = handle.sent();
+        assert_eq!(
+            sent[..3],
+            [
+                Commands::GetConfig(GetConfigCommand { id: 1 }),
+                Commands::GetConfig(GetConfigCommand { id: 2 }),
+                Commands::SetValue(SetValueCommand { id: 5, value: 42 }),
+            ]
+        );
+
+        // The scan continues afterwards
+        for receiver in scan {
+            assert!(matches!(receiver.await.unwrap(), RequestResponse::Timeout));
+        }
+        assert_eq!(handle.sent().len(), 21);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_coalesce_set_value() {
+        let (mut controller, handle) = start(vec![
+            Reply::after(20, vec![value(5, 10)]),
+            Reply::after(20, vec![value(5, 60)]),
+        ]);
+
+        let first = controller.set_value(5, 10).await;
+        // Let the first value go out, the slider keeps moving while it is in flight
+        sleep(Duration::from_millis(1)).await;
+
+        let mut burst = Vec::new();
+        for value in 2..=6 {
+            burst.push(controller.set_value(5, value * 10).await);
+        }
+
+        assert_eq!(unwrap(first.await.unwrap()).value, 10);
+        for receiver in burst {
+            assert_eq!(unwrap(receiver.await.unwrap()).value, 60);
+        }
+
+        assert_eq!(
+            handle.sent(),
+            vec![
+                Commands::SetValue(SetValueCommand { id: 5, value: 10 }),
+                Commands::SetValue(SetValueCommand { id: 5, value: 60 }),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_newer_value_drops_retry() {
+        let (mut controller, handle) =
+            start(vec![Reply::silence(), Reply::after(20, vec![value(5, 20)])]);
+
+        let start = Instant::now();
+        let first = controller.set_value(5, 10).await;
+        // The first value timed out and waits for its retry
+        sleep(Duration::from_millis(550)).await;
+        let second = controller.set_value(5, 20).await;
+
+        assert_eq!(unwrap(second.await.unwrap()).value, 20);
+        assert_eq!(unwrap(first.await.unwrap()).value, 20);
+        assert_eq!(start.elapsed(), Duration::from_millis(570));
+
+        // The retry of the stale value is never sent
+        sleep(Duration::from_millis(5000)).await;
+        assert_eq!(
+            handle.sent(),
+            vec![
+                Commands::SetValue(SetValueCommand { id: 5, value: 10 }),
+                Commands::SetValue(SetValueCommand { id: 5, value: 20 }),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry() {
+        let (mut controller, handle) =
+            start(vec![Reply::silence(), Reply::after(20, vec![value(5, 42)])]);
+
+        let start = Instant::now();
+        let response = controller.set_value(5, 42).await.await.unwrap();
+        assert!(matches!(
+            response,
+            RequestResponse::Response {
+                value: Value { id: 5, value: 42 },
+                attempts: 2
+            }
+        ));
+        assert_eq!(start.elapsed(), Duration::from_millis(620));
+        assert_eq!(handle.sent().len(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_interactive_during_backoff() {
+        let (mut controller, handle) = start_with(
+            with_poll_retries(),
+            vec![Reply::silence(), Reply::after(20, vec![value(6, 1)])],
+        );
+
+        let start = Instant::now();
+        let polling = controller.request_current_value(5).await;
+        sleep(Duration::from_millis(100)).await;
+
+        // The set goes out between the timeout and the retry of the poll
+        unwrap(controller.set_value(6, 1).await.await.unwrap());
+        assert_eq!(start.elapsed(), Duration::from_millis(520));
+
+        assert!(matches!(polling.await.unwrap(), RequestResponse::Timeout));
+        assert_eq!(
+            handle.sent(),
+            vec![
+                Commands::GetValue(GetValueCommand { id: 5 }),
+                Commands::SetValue(SetValueCommand { id: 6, value: 1 }),
+                Commands::GetValue(GetValueCommand { id: 5 }),
+                Commands::GetValue(GetValueCommand { id: 5 }),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries_exhausted() {
+        let (mut controller, handle) = start_with(with_poll_retries(), vec![]);
+
+        let start = Instant::now();
+        let response = controller.request_current_value(5).await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+        // Three timeouts with a backoff of 100 ms and 200 ms in between
+        assert_eq!(start.elapsed(), Duration::from_millis(1800));
+        assert_eq!(
+            handle.sent(),
+            vec![Commands::GetValue(GetValueCommand { id: 5 }); 3]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_unsafe_to_retry() {
+        let (mut controller, handle) = start_with(
+            json!({ "retries": { "setValue": { "safeToRetry": false } } }),
+            vec![],
+        );
+
+        let response = controller.deactivate_scene(3).await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+        let response = controller.set_value(5, 42).await.await.unwrap();
+        assert!(matches!(response, RequestResponse::Timeout));
+        assert_eq!(handle.sent().len(), 2);
+    }
+}
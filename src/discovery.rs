@@ -60,7 +60,7 @@ impl Discovery {
                     let receiver = controller.request_config(id).await;
 
                     match receiver.await {
-                        Ok(RequestResponse::Response(config)) => {
+                        Ok(RequestResponse::Response { value: config, .. }) => {
                             log::debug!("Received config for {}", id);
                             known_ids.insert(config.id);
                         }
@@ -79,7 +79,9 @@ impl Discovery {
                     let receiver = controller.hail().await;
 
                     match receiver.await {
-                        Ok(RequestResponse::Response(hail_config)) => {
+                        Ok(RequestResponse::Response {
+                            value: hail_config, ..
+                        }) => {
                             log::debug!(
                                 "Received hail config for {}",
                                 hail_config.hardware_serial_number
@@ -92,7 +94,7 @@ impl Discovery {
                                         .await;
 
                                     match receiver.await {
-                                        Ok(RequestResponse::Response(_)) => {
+                                        Ok(RequestResponse::Response { .. }) => {
                                             log::debug!("Received assigned id config for {}", id);
                                         }
                                         Ok(RequestResponse::Timeout) => {
@@ -4,12 +4,15 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 mod adapter;
+mod backoff;
 mod config;
 mod controller;
 mod discovery;
 mod protocol;
+mod queue;
 mod request;
 mod scenes;
+mod simulator;
 mod throttle;
 mod transport;
 mod zones;
@@ -17,23 +20,14 @@ mod zones;
 use crate::adapter::{BuiltLumenCacheAdapter, LumenCacheAdapter};
 use crate::config::Config;
 use crate::controller::Controller;
-use crate::transport::{SerialTransport, TcpTransport, Transport};
-use anyhow::{anyhow, Error, Result};
+use crate::transport::{start_links, Link, TransportEvent};
+use anyhow::{Error, Result};
 use as_any::Downcast;
-use futures::prelude::stream::SplitStream;
-use futures::prelude::*;
 use gateway_addon_rust::error::WebthingsError;
 use gateway_addon_rust::plugin::{connect, Plugin};
 use log::LevelFilter;
-use protocol::codec::LumenCacheCodec;
 use simple_logger::SimpleLogger;
-use std::sync::Arc;
 use std::time::Duration;
-use tokio::io::AsyncRead;
-use tokio::net::TcpStream;
-use tokio::sync::Mutex;
-use tokio_serial::SerialPortBuilderExt;
-use tokio_util::codec::Framed;
 
 #[tokio::main]
 async fn main() {
@@ -42,6 +36,16 @@ async fn main() {
         .init()
         .unwrap();
 
+    let args: Vec<String> = std::env::args().collect();
+
+    if args.get(1).map(String::as_str) == Some("simulate") {
+        if let Err(err) = simulator::run(&args[2..]).await {
+            log::error!("Could not start simulator: {}", err);
+        }
+
+        return;
+    }
+
     if let Err(err) = run().await {
         log::error!("Could not start adapter: {}", err);
     }
@@ -58,41 +62,13 @@ async fn run() -> Result<(), Error> {
 
     if let Some(conf) = conf {
         log::debug!("Loaded config {:?}", conf);
+        conf.validate()?;
         database.save_config(&conf).unwrap();
 
-        for adapter_config in conf.clone().serial_adapters {
-            let id = adapter_config.id.clone();
-
-            let title = adapter_config.title.clone();
-
-            log::debug!("Creating adapter '{}' ({})", title, id);
-
-            let stream = tokio_serial::new(adapter_config.port, 38400)
-                .open_native_async()
-                .map_err(|err| anyhow!(err))?;
-            let stream = Framed::new(stream, LumenCacheCodec);
-            let (sink, stream) = stream.split();
-            let transport = Arc::new(Mutex::new(SerialTransport::new(sink)));
-
-            create_adapter(conf.clone(), &mut plugin, &id, &title, stream, transport).await?;
-        }
-
-        for adapter_config in conf.clone().tcp_adapters {
-            let id = adapter_config.id.clone();
+        for link in start_links(&conf) {
+            log::debug!("Creating adapter '{}' ({})", link.title, link.id);
 
-            let title = adapter_config.title.clone();
-
-            log::debug!("Creating tcp adapter '{}' ({})", title, id);
-
-            let stream =
-                TcpStream::connect(format!("{}:{}", adapter_config.host, adapter_config.port))
-                    .await
-                    .unwrap();
-            let stream = Framed::new(stream, LumenCacheCodec);
-            let (sink, stream) = stream.split();
-            let transport = Arc::new(Mutex::new(TcpTransport::new(sink)));
-
-            create_adapter(conf.clone(), &mut plugin, &id, &title, stream, transport).await?;
+            create_adapter(conf.clone(), &mut plugin, link).await?;
         }
     }
 
@@ -101,38 +77,46 @@ async fn run() -> Result<(), Error> {
     Ok(())
 }
 
-async fn create_adapter<T>(
+async fn create_adapter(
     config: Config,
     plugin: &mut Plugin,
-    id: &str,
-    title: &str,
-    mut stream: SplitStream<Framed<T, LumenCacheCodec>>,
-    transport: Arc<Mutex<dyn Transport>>,
-) -> Result<(), WebthingsError>
-where
-    T: AsyncRead + Send + 'static,
-{
+    link: Link,
+) -> Result<(), WebthingsError> {
+    let Link {
+        id,
+        title,
+        transport,
+        mut events,
+    } = link;
+
     let controller = Controller::start(config.clone(), transport);
     let adapter = plugin
         .add_adapter(LumenCacheAdapter::new(
-            id.to_owned(),
-            title.to_owned(),
+            id,
+            title.clone(),
             config,
             controller.clone(),
         ))
         .await?;
 
-    let adapter_clone = adapter.clone();
+    if let Err(err) = adapter
+        .lock()
+        .await
+        .on_start_pairing(Duration::from_secs(120))
+        .await
+    {
+        log::error!("Failed to start pairing: {}", err);
+    }
 
     tokio::spawn(async move {
-        loop {
-            match stream.next().await {
-                Some(Ok(response)) => {
+        while let Some(event) = events.recv().await {
+            match event {
+                TransportEvent::Response(response) => {
                     log::debug!("Received {:?}", response);
 
                     controller.check_response(&response).await;
 
-                    adapter_clone
+                    adapter
                         .lock()
                         .await
                         .downcast_mut::<BuiltLumenCacheAdapter>()
@@ -140,24 +124,41 @@ where
                         .on_message(response)
                         .await;
                 }
-                Some(Err(err)) => {
-                    panic!("Failed to get response: {}", err);
+                TransportEvent::Connected => {
+                    log::info!("Adapter '{}' connected", title);
+
+                    adapter
+                        .lock()
+                        .await
+                        .downcast_mut::<BuiltLumenCacheAdapter>()
+                        .unwrap()
+                        .on_connected()
+                        .await;
+                }
+                TransportEvent::Command(command) => {
+                    adapter
+                        .lock()
+                        .await
+                        .downcast_mut::<BuiltLumenCacheAdapter>()
+                        .unwrap()
+                        .on_command(command)
+                        .await;
                 }
-                None => {
-                    panic!("End of stream");
+                TransportEvent::Disconnected => {
+                    controller.on_disconnected();
+
+                    let stats = controller.throttle_stats();
+                    log::warn!(
+                        "Adapter '{}' disconnected (gap {:?}, smoothed RTT {:?}, timeout rate {:.2})",
+                        title,
+                        stats.gap,
+                        stats.smoothed_rtt,
+                        stats.timeout_rate
+                    );
                 }
             }
         }
     });
 
-    if let Err(err) = adapter
-        .lock()
-        .await
-        .on_start_pairing(Duration::from_secs(120))
-        .await
-    {
-        log::error!("Failed to start pairing: {}", err);
-    }
-
     Ok(())
 }
@@ -3,4 +3,13 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
+/// Codec for the controller side of the bus, encodes `Commands` and decodes `Response`s
+#[derive(Clone, Copy)]
 pub struct LumenCacheCodec;
+
+/// Codec for the module side of the bus, decodes `Commands` and encodes `Response`s
+pub struct LumenCacheDeviceCodec;
+
+/// Codec for listening to the traffic of another controller, decodes `Commands` and `Response`s
+#[derive(Clone, Copy)]
+pub struct LumenCacheMonitorCodec;
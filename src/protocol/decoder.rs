@@ -3,11 +3,11 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::protocol::codec::LumenCacheCodec;
+use crate::protocol::codec::{LumenCacheCodec, LumenCacheDeviceCodec};
 use anyhow::{anyhow, Error, Result};
-use bytes::{Buf, BytesMut};
-use std::fmt::Debug;
-use tokio_util::codec::Decoder;
+use bytes::{Buf, BufMut, BytesMut};
+use std::fmt::{self, Debug, Display, Formatter};
+use tokio_util::codec::{Decoder, Encoder};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Response {
@@ -15,6 +15,8 @@ pub enum Response {
     SerialNumber(SerialNumber),
     Scene(Scene),
     Config(Config),
+    Malformed { raw: String, reason: String },
+    Unknown(Vec<String>),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -40,13 +42,13 @@ pub struct Scene {
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct Config {
     pub id: u8,
-    pub hardware_type: u8,
+    pub hardware_type: HardwareType,
     pub hardware_version: u8,
     pub firmware_version: String,
     pub hardware_serial_number: String,
-    pub mode: u8,
-    pub dimming_curve: u8,
-    pub pwm_frequency: u8,
+    pub mode: Mode,
+    pub dimming_curve: DimmingCurve,
+    pub pwm_frequency: PwmFrequency,
     pub minimum_output_pwm: u8,
     pub maximum_output_pwm: u8,
     pub resume_level: u8,
@@ -56,11 +58,243 @@ pub struct Config {
     pub inverted_output: u8,
 }
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HardwareType {
+    Dimmer,
+    Relay,
+    Input,
+    LedDriver,
+    Unknown(u8),
+}
+
+impl HardwareType {
+    pub const ALL: [HardwareType; 4] = [
+        HardwareType::Dimmer,
+        HardwareType::Relay,
+        HardwareType::Input,
+        HardwareType::LedDriver,
+    ];
+}
+
+impl From<u8> for HardwareType {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => HardwareType::Dimmer,
+            2 => HardwareType::Relay,
+            3 => HardwareType::Input,
+            7 => HardwareType::LedDriver,
+            value => HardwareType::Unknown(value),
+        }
+    }
+}
+
+impl From<HardwareType> for u8 {
+    fn from(value: HardwareType) -> Self {
+        match value {
+            HardwareType::Dimmer => 1,
+            HardwareType::Relay => 2,
+            HardwareType::Input => 3,
+            HardwareType::LedDriver => 7,
+            HardwareType::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for HardwareType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            HardwareType::Dimmer => write!(f, "Dimmer"),
+            HardwareType::Relay => write!(f, "Relay"),
+            HardwareType::Input => write!(f, "Input"),
+            HardwareType::LedDriver => write!(f, "LED driver"),
+            HardwareType::Unknown(value) => write!(f, "Unknown ({})", value),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Mode {
+    Dimmer,
+    Switch,
+    MotionSensor,
+    Input,
+    Relay,
+    AlternateActions,
+    Unknown(u8),
+}
+
+impl Mode {
+    pub const ALL: [Mode; 6] = [
+        Mode::Dimmer,
+        Mode::Switch,
+        Mode::MotionSensor,
+        Mode::Input,
+        Mode::Relay,
+        Mode::AlternateActions,
+    ];
+}
+
+impl From<u8> for Mode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Mode::Dimmer,
+            2 => Mode::Switch,
+            3 => Mode::MotionSensor,
+            4 => Mode::Input,
+            5 => Mode::Relay,
+            6 => Mode::AlternateActions,
+            value => Mode::Unknown(value),
+        }
+    }
+}
+
+impl From<Mode> for u8 {
+    fn from(value: Mode) -> Self {
+        match value {
+            Mode::Dimmer => 1,
+            Mode::Switch => 2,
+            Mode::MotionSensor => 3,
+            Mode::Input => 4,
+            Mode::Relay => 5,
+            Mode::AlternateActions => 6,
+            Mode::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for Mode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Mode::Dimmer => write!(f, "Mode 1: dimmer"),
+            Mode::Switch => write!(f, "Mode 2: switch"),
+            Mode::MotionSensor => write!(f, "Mode 3: motion sensor"),
+            Mode::Input => write!(f, "Mode 4: input"),
+            Mode::Relay => write!(f, "Mode 5: relay"),
+            Mode::AlternateActions => write!(f, "Mode 6: alternate actions"),
+            Mode::Unknown(value) => write!(f, "Mode {}: unknown", value),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DimmingCurve {
+    Linear,
+    Logarithmic,
+    Exponential,
+    Unknown(u8),
+}
+
+impl DimmingCurve {
+    pub const ALL: [DimmingCurve; 3] = [
+        DimmingCurve::Linear,
+        DimmingCurve::Logarithmic,
+        DimmingCurve::Exponential,
+    ];
+}
+
+impl From<u8> for DimmingCurve {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => DimmingCurve::Linear,
+            1 => DimmingCurve::Logarithmic,
+            2 => DimmingCurve::Exponential,
+            value => DimmingCurve::Unknown(value),
+        }
+    }
+}
+
+impl From<DimmingCurve> for u8 {
+    fn from(value: DimmingCurve) -> Self {
+        match value {
+            DimmingCurve::Linear => 0,
+            DimmingCurve::Logarithmic => 1,
+            DimmingCurve::Exponential => 2,
+            DimmingCurve::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for DimmingCurve {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            DimmingCurve::Linear => write!(f, "Linear"),
+            DimmingCurve::Logarithmic => write!(f, "Logarithmic"),
+            DimmingCurve::Exponential => write!(f, "Exponential"),
+            DimmingCurve::Unknown(value) => write!(f, "Unknown ({})", value),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum PwmFrequency {
+    Hz250,
+    Hz500,
+    Hz1000,
+    Hz2000,
+    Unknown(u8),
+}
+
+impl PwmFrequency {
+    pub const ALL: [PwmFrequency; 4] = [
+        PwmFrequency::Hz250,
+        PwmFrequency::Hz500,
+        PwmFrequency::Hz1000,
+        PwmFrequency::Hz2000,
+    ];
+}
+
+impl From<u8> for PwmFrequency {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => PwmFrequency::Hz250,
+            1 => PwmFrequency::Hz500,
+            2 => PwmFrequency::Hz1000,
+            3 => PwmFrequency::Hz2000,
+            value => PwmFrequency::Unknown(value),
+        }
+    }
+}
+
+impl From<PwmFrequency> for u8 {
+    fn from(value: PwmFrequency) -> Self {
+        match value {
+            PwmFrequency::Hz250 => 0,
+            PwmFrequency::Hz500 => 1,
+            PwmFrequency::Hz1000 => 2,
+            PwmFrequency::Hz2000 => 3,
+            PwmFrequency::Unknown(value) => value,
+        }
+    }
+}
+
+impl Display for PwmFrequency {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PwmFrequency::Hz250 => write!(f, "250 Hz"),
+            PwmFrequency::Hz500 => write!(f, "500 Hz"),
+            PwmFrequency::Hz1000 => write!(f, "1 kHz"),
+            PwmFrequency::Hz2000 => write!(f, "2 kHz"),
+            PwmFrequency::Unknown(value) => write!(f, "Unknown ({})", value),
+        }
+    }
+}
+
 const BEGIN_RESPONSE: u8 = b'(';
 const END_RESPONSE: u8 = b')';
 const BEGIN_CONFIG: u8 = b'{';
 const END_CONFIG: u8 = b'}';
 
+/// Partial frames longer than this are considered to be noise and are discarded
+pub const MAX_FRAME_LENGTH: usize = 128;
+
+fn is_begin(b: u8) -> bool {
+    b == BEGIN_RESPONSE || b == BEGIN_CONFIG
+}
+
+fn is_delimiter(b: u8) -> bool {
+    is_begin(b) || b == END_RESPONSE || b == END_CONFIG
+}
+
 impl Decoder for LumenCacheCodec {
     type Item = Response;
     type Error = Error;
@@ -71,58 +305,86 @@ impl Decoder for LumenCacheCodec {
 }
 
 impl LumenCacheCodec {
+    /// Parses the next complete frame in `buf`.
+    ///
+    /// Frames which cannot be parsed are consumed and returned as `Response::Malformed`, so
+    /// that the caller is able to continue with the frames behind them.
     pub fn parse(buf: &mut BytesMut) -> Option<Response> {
-        let begin = buf
-            .iter()
-            .position(|b| *b == BEGIN_RESPONSE || *b == BEGIN_CONFIG)?;
-
-        match buf[begin] {
-            BEGIN_RESPONSE => {
-                let end = buf.iter().position(|b| *b == END_RESPONSE)?;
-                let response = LumenCacheCodec::extract(buf, begin, end)?;
-
-                match LumenCacheCodec::parse_value(&response) {
-                    Ok(value) => Some(value),
-                    Err(err) => {
-                        log::error!("Failed to parse {}: {}", response, err);
-                        None
-                    }
+        let begin = match buf.iter().position(|b| is_begin(*b)) {
+            Some(begin) => begin,
+            None => {
+                buf.clear();
+                return None;
+            }
+        };
+
+        buf.advance(begin);
+
+        let end_delimiter = match buf[0] {
+            BEGIN_RESPONSE => END_RESPONSE,
+            _ => END_CONFIG,
+        };
+
+        let end = match buf.iter().skip(1).position(|b| is_delimiter(*b)) {
+            Some(position) => position + 1,
+            None => {
+                if buf.len() > MAX_FRAME_LENGTH {
+                    let bytes = buf.split_to(buf.len());
+
+                    return Some(malformed(
+                        String::from_utf8_lossy(&bytes[1..]).into_owned(),
+                        format!("Frame exceeds {} bytes", MAX_FRAME_LENGTH),
+                    ));
                 }
+
+                return None;
             }
-            BEGIN_CONFIG => {
-                let end = buf.iter().position(|b| *b == END_CONFIG)?;
-                let response = LumenCacheCodec::extract(buf, begin, end)?;
+        };
+
+        if is_begin(buf[end]) {
+            let bytes = buf.split_to(end);
+
+            return Some(malformed(
+                String::from_utf8_lossy(&bytes[1..]).into_owned(),
+                String::from("Frame was interrupted by the start of another frame"),
+            ));
+        }
+
+        if buf[end] != end_delimiter {
+            let bytes = buf.split_to(end + 1);
+
+            return Some(malformed(
+                String::from_utf8_lossy(&bytes[1..end]).into_owned(),
+                format!("Frame was terminated by {:?}", bytes[end] as char),
+            ));
+        }
+
+        let begin_delimiter = buf[0];
+
+        let response = match LumenCacheCodec::extract(buf, 0, end) {
+            Ok(response) => response,
+            Err(malformed) => return Some(malformed),
+        };
+
+        match begin_delimiter {
+            BEGIN_RESPONSE => Some(or_malformed(
+                &response,
+                LumenCacheCodec::parse_value(&response),
+            )),
+            _ => {
                 let parts: Vec<&str> = response.split(',').map(|s| s.trim()).collect();
 
-                match parts.len() {
-                    2 => match LumenCacheCodec::parse_serial(parts) {
-                        Ok(value) => Some(value),
-                        Err(err) => {
-                            log::error!("Failed to parse {}: {}", response, err);
-                            None
-                        }
-                    },
-                    4 => match LumenCacheCodec::parse_scene(parts) {
-                        Ok(value) => Some(value),
-                        Err(err) => {
-                            log::error!("Failed to parse {}: {}", response, err);
-                            None
-                        }
-                    },
-                    15 => match LumenCacheCodec::parse_config(parts) {
-                        Ok(value) => Some(value),
-                        Err(err) => {
-                            log::error!("Failed to parse {}: {}", response, err);
-                            None
-                        }
-                    },
-                    nr => {
-                        log::warn!("Unexpected number of parts ({}) in config response", nr);
-                        None
-                    }
-                }
+                let result = match parts.len() {
+                    2 => LumenCacheCodec::parse_serial(parts),
+                    4 => LumenCacheCodec::parse_scene(parts),
+                    15 => LumenCacheCodec::parse_config(parts),
+                    _ => Ok(Response::Unknown(
+                        parts.iter().map(|part| (*part).to_owned()).collect(),
+                    )),
+                };
+
+                Some(or_malformed(&response, result))
             }
-            _ => None,
         }
     }
 
@@ -168,13 +430,13 @@ impl LumenCacheCodec {
 
         Ok(Response::Config(Config {
             id: parts[0].parse()?,
-            hardware_type: parts[1].parse()?,
+            hardware_type: parts[1].parse::<u8>()?.into(),
             hardware_version: parts[2].parse()?,
             firmware_version: parts[3].to_owned(),
             hardware_serial_number: parts[4].to_owned(),
-            mode: parts[5].parse()?,
-            dimming_curve: parts[6].parse()?,
-            pwm_frequency: parts[7].parse()?,
+            mode: parts[5].parse::<u8>()?.into(),
+            dimming_curve: parts[6].parse::<u8>()?.into(),
+            pwm_frequency: parts[7].parse::<u8>()?.into(),
             minimum_output_pwm: parts[8].parse()?,
             maximum_output_pwm: parts[9].parse()?,
             resume_level: parts[10].parse()?,
@@ -185,21 +447,80 @@ impl LumenCacheCodec {
         }))
     }
 
-    pub fn extract(buf: &mut BytesMut, begin: usize, end: usize) -> Option<String> {
+    pub fn extract(buf: &mut BytesMut, begin: usize, end: usize) -> Result<String, Response> {
         let mut bytes = buf.split_to(end + 1);
         bytes.advance(begin + 1);
         bytes.truncate(bytes.len() - 1);
 
-        match String::from_utf8(bytes.to_vec()) {
-            Ok(response) => Some(response),
-            Err(err) => {
-                log::error!("Could not decode response {:?}: {}", bytes, err);
-                None
+        String::from_utf8(bytes.to_vec()).map_err(|err| {
+            malformed(
+                String::from_utf8_lossy(&bytes).into_owned(),
+                err.to_string(),
+            )
+        })
+    }
+}
+
+impl Encoder<Response> for LumenCacheDeviceCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: Response, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match item {
+            Response::Value(Value { id, value }) => {
+                dst.put(format!("({},{})", id, value).as_bytes())
             }
-        }
+            Response::SerialNumber(SerialNumber { id, serial_number }) => {
+                dst.put(format!("{{{},{}}}", id, serial_number).as_bytes())
+            }
+            Response::Scene(Scene {
+                id,
+                scene,
+                level,
+                duration,
+            }) => dst.put(format!("{{{},{},{},{}}}", id, scene, level, duration).as_bytes()),
+            Response::Config(config) => dst.put(
+                format!(
+                    "{{{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}}}",
+                    config.id,
+                    u8::from(config.hardware_type),
+                    config.hardware_version,
+                    config.firmware_version,
+                    config.hardware_serial_number,
+                    u8::from(config.mode),
+                    u8::from(config.dimming_curve),
+                    u8::from(config.pwm_frequency),
+                    config.minimum_output_pwm,
+                    config.maximum_output_pwm,
+                    config.resume_level,
+                    config.ramp_duration,
+                    config.motion_sensor_enable,
+                    config.mode_6_alternate_actions,
+                    config.inverted_output
+                )
+                .as_bytes(),
+            ),
+            Response::Malformed { raw, .. } => {
+                return Err(anyhow!("Cannot encode malformed frame {}", raw))
+            }
+            Response::Unknown(parts) => dst.put(format!("{{{}}}", parts.join(",")).as_bytes()),
+        };
+
+        Ok(())
     }
 }
 
+fn or_malformed(raw: &str, result: Result<Response>) -> Response {
+    match result {
+        Ok(response) => response,
+        Err(err) => malformed(raw.to_owned(), err.to_string()),
+    }
+}
+
+fn malformed(raw: String, reason: String) -> Response {
+    log::warn!("Failed to parse {}: {}", raw, reason);
+    Response::Malformed { raw, reason }
+}
+
 fn assert_length<T: Debug>(parts: &[T], length: usize) -> Result<()> {
     if parts.len() != length {
         return Err(anyhow!(
@@ -216,12 +537,14 @@ fn assert_length<T: Debug>(parts: &[T], length: usize) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::encoder::{Commands, ConfigField, SetConfigCommand};
+    use proptest::prelude::*;
 
     #[test]
     fn test_no_begin() {
         let mut buf = BytesMut::from(&b"foo"[..]);
         assert_eq!(LumenCacheCodec::parse(&mut buf), None);
-        assert_eq!(buf.to_vec(), &b"foo"[..]);
+        assert_eq!(buf.to_vec(), &b""[..]);
     }
 
     #[test]
@@ -244,10 +567,142 @@ mod tests {
     #[test]
     fn test_multiple_delimiters() {
         let mut buf = BytesMut::from(&b"(13,,37)"[..]);
-        assert_eq!(LumenCacheCodec::parse(&mut buf), None);
+        assert!(matches!(
+            LumenCacheCodec::parse(&mut buf),
+            Some(Response::Malformed { raw, .. }) if raw == "13,,37"
+        ));
         assert_eq!(buf.to_vec(), &b""[..]);
     }
 
+    #[test]
+    fn test_valid_after_malformed() {
+        let mut codec = LumenCacheCodec;
+        let mut buf = BytesMut::from(&b"(13,,37){1,x,0,0}(42,24)"[..]);
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Response::Malformed { .. })
+        ));
+        assert!(matches!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Response::Malformed { raw, .. }) if raw == "1,x,0,0"
+        ));
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Response::Value(Value { id: 42, value: 24 }))
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+    }
+
+    fn malformed(raw: &str) -> Response {
+        Response::Malformed {
+            raw: raw.to_owned(),
+            reason: String::new(),
+        }
+    }
+
+    fn value(id: u8, value: u8) -> Response {
+        Response::Value(Value { id, value })
+    }
+
+    #[test]
+    fn test_corruption() {
+        let long = format!("({}", "1".repeat(MAX_FRAME_LENGTH));
+
+        let cases: Vec<(&[u8], Vec<Response>, &[u8])> = vec![
+            (b"noise", vec![], b""),
+            (b"noise(13,37)", vec![value(13, 37)], b""),
+            (b"(13,", vec![], b"(13,"),
+            (b"noise(13,", vec![], b"(13,"),
+            (b"(12(13,37)", vec![malformed("12"), value(13, 37)], b""),
+            (b"{1,2(13,37)", vec![malformed("1,2"), value(13, 37)], b""),
+            (
+                b"(12{1,2,3,4}",
+                vec![
+                    malformed("12"),
+                    Response::Scene(Scene {
+                        id: 1,
+                        scene: 2,
+                        level: 3,
+                        duration: 4,
+                    }),
+                ],
+                b"",
+            ),
+            (
+                b"(13,37}(42,24)",
+                vec![malformed("13,37"), value(42, 24)],
+                b"",
+            ),
+            (
+                b"{1,1,100,20)(42,24)",
+                vec![malformed("1,1,100,20"), value(42, 24)],
+                b"",
+            ),
+            (b"){(13,37)", vec![malformed(""), value(13, 37)], b""),
+            (b"(13,37))(42,24)", vec![value(13, 37), value(42, 24)], b""),
+            (long.as_bytes(), vec![malformed(&long[1..])], b""),
+        ];
+
+        for (input, expected, remaining) in cases {
+            let mut buf = BytesMut::from(input);
+            let mut responses = Vec::new();
+
+            while let Some(response) = LumenCacheCodec::parse(&mut buf) {
+                responses.push(match response {
+                    Response::Malformed { raw, .. } => malformed(&raw),
+                    response => response,
+                });
+            }
+
+            assert_eq!(responses, expected, "{}", String::from_utf8_lossy(input));
+            assert_eq!(
+                buf.to_vec(),
+                remaining,
+                "{}",
+                String::from_utf8_lossy(input)
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        let mut buf = BytesMut::from(&b"(13,\xff)(42,24)"[..]);
+        assert!(matches!(
+            LumenCacheCodec::parse(&mut buf),
+            Some(Response::Malformed { .. })
+        ));
+        assert_eq!(
+            LumenCacheCodec::parse(&mut buf),
+            Some(Response::Value(Value { id: 42, value: 24 }))
+        );
+    }
+
+    #[test]
+    fn test_unexpected_number_of_parts() {
+        let mut buf = BytesMut::from(&b"{1, 2,3}"[..]);
+        assert_eq!(
+            LumenCacheCodec::parse(&mut buf),
+            Some(Response::Unknown(vec![
+                String::from("1"),
+                String::from("2"),
+                String::from("3")
+            ]))
+        );
+        assert_eq!(buf.to_vec(), &b""[..]);
+    }
+
+    #[test]
+    fn test_serial_number() {
+        let mut buf = BytesMut::from(&b"{13,0123456789ABCDEF0123}"[..]);
+        assert_eq!(
+            LumenCacheCodec::parse(&mut buf),
+            Some(Response::SerialNumber(SerialNumber {
+                id: 13,
+                serial_number: String::from("0123456789ABCDEF0123")
+            }))
+        );
+    }
+
     #[test]
     fn test_spaces() {
         let mut buf = BytesMut::from(&b" ( 13 , 37 ) "[..]);
@@ -286,13 +741,13 @@ mod tests {
             LumenCacheCodec::parse(&mut buf),
             Some(Response::Config(Config {
                 id: 1,
-                hardware_type: 7,
+                hardware_type: HardwareType::LedDriver,
                 hardware_version: 3,
                 firmware_version: String::from("123456.12"),
                 hardware_serial_number: String::from("0123456789ABCDEF0123"),
-                mode: 6,
-                dimming_curve: 1,
-                pwm_frequency: 2,
+                mode: Mode::AlternateActions,
+                dimming_curve: DimmingCurve::Logarithmic,
+                pwm_frequency: PwmFrequency::Hz1000,
                 minimum_output_pwm: 30,
                 maximum_output_pwm: 220,
                 resume_level: 255,
@@ -304,4 +759,191 @@ mod tests {
         );
         assert_eq!(buf.to_vec(), &b""[..]);
     }
+
+    #[test]
+    fn test_config_unknown_enums() {
+        let mut buf = BytesMut::from(
+            &b"{1,42,3,123456.12,0123456789ABCDEF0123,43,44,45,30,220,255,0,1,0,0}"[..],
+        );
+
+        match LumenCacheCodec::parse(&mut buf) {
+            Some(Response::Config(config)) => {
+                assert_eq!(config.hardware_type, HardwareType::Unknown(42));
+                assert_eq!(config.mode, Mode::Unknown(43));
+                assert_eq!(config.dimming_curve, DimmingCurve::Unknown(44));
+                assert_eq!(config.pwm_frequency, PwmFrequency::Unknown(45));
+            }
+            other => panic!("Expected config but got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_enum_conversions() {
+        for mode in Mode::ALL.iter() {
+            assert_eq!(Mode::from(u8::from(*mode)), *mode);
+        }
+
+        for hardware_type in HardwareType::ALL.iter() {
+            assert_eq!(HardwareType::from(u8::from(*hardware_type)), *hardware_type);
+        }
+
+        for dimming_curve in DimmingCurve::ALL.iter() {
+            assert_eq!(DimmingCurve::from(u8::from(*dimming_curve)), *dimming_curve);
+        }
+
+        for pwm_frequency in PwmFrequency::ALL.iter() {
+            assert_eq!(PwmFrequency::from(u8::from(*pwm_frequency)), *pwm_frequency);
+        }
+
+        assert_eq!(Mode::from(6).to_string(), "Mode 6: alternate actions");
+        assert_eq!(Mode::from(42).to_string(), "Mode 42: unknown");
+    }
+
+    #[test]
+    fn test_config_write_read_back() {
+        let frame = "1,7,3,123456.12,0123456789ABCDEF0123,6,1,2,30,220,255,0,1,0,0";
+
+        for field in ConfigField::ALL.iter() {
+            let mut encoder = LumenCacheCodec;
+            let mut command = BytesMut::new();
+            encoder
+                .encode(
+                    Commands::SetConfig(SetConfigCommand {
+                        id: 1,
+                        field: *field,
+                        value: 42,
+                    }),
+                    &mut command,
+                )
+                .unwrap();
+            assert_eq!(
+                command.to_vec(),
+                format!("[1,2{:02}042]", field.index()).as_bytes()
+            );
+
+            let mut parts: Vec<&str> = frame.split(',').collect();
+            parts[field.index() as usize] = "42";
+            let mut buf = BytesMut::from(format!("{{{}}}", parts.join(",")).as_bytes());
+
+            match LumenCacheCodec::parse(&mut buf) {
+                Some(Response::Config(config)) => assert_eq!(field.read(&config), 42),
+                other => panic!("Expected config but got {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_value() {
+        let mut buf = BytesMut::new();
+        LumenCacheDeviceCodec
+            .encode(Response::Value(Value { id: 13, value: 37 }), &mut buf)
+            .unwrap();
+        assert_eq!(buf.to_vec(), &b"(13,37)"[..]);
+    }
+
+    #[test]
+    fn test_encode_malformed() {
+        let mut buf = BytesMut::new();
+        assert!(LumenCacheDeviceCodec
+            .encode(
+                Response::Malformed {
+                    raw: String::from("13,,37"),
+                    reason: String::new()
+                },
+                &mut buf
+            )
+            .is_err());
+    }
+
+    fn config() -> impl Strategy<Value = Config> {
+        (
+            (
+                any::<u8>(),
+                any::<u8>(),
+                any::<u8>(),
+                "[0-9]{6}\\.[0-9]{2}",
+                "[0-9A-F]{20}",
+                any::<u8>(),
+                any::<u8>(),
+                any::<u8>(),
+            ),
+            (
+                any::<u8>(),
+                any::<u8>(),
+                any::<u8>(),
+                any::<u8>(),
+                any::<u8>(),
+                any::<u8>(),
+                any::<u8>(),
+            ),
+        )
+            .prop_map(
+                |(
+                    (
+                        id,
+                        hardware_type,
+                        hardware_version,
+                        firmware_version,
+                        hardware_serial_number,
+                        mode,
+                        dimming_curve,
+                        pwm_frequency,
+                    ),
+                    (
+                        minimum_output_pwm,
+                        maximum_output_pwm,
+                        resume_level,
+                        ramp_duration,
+                        motion_sensor_enable,
+                        mode_6_alternate_actions,
+                        inverted_output,
+                    ),
+                )| Config {
+                    id,
+                    hardware_type: hardware_type.into(),
+                    hardware_version,
+                    firmware_version,
+                    hardware_serial_number,
+                    mode: mode.into(),
+                    dimming_curve: dimming_curve.into(),
+                    pwm_frequency: pwm_frequency.into(),
+                    minimum_output_pwm,
+                    maximum_output_pwm,
+                    resume_level,
+                    ramp_duration,
+                    motion_sensor_enable,
+                    mode_6_alternate_actions,
+                    inverted_output,
+                },
+            )
+    }
+
+    fn response() -> impl Strategy<Value = Response> {
+        prop_oneof![
+            (any::<u8>(), any::<u8>()).prop_map(|(id, value)| Response::Value(Value { id, value })),
+            (any::<u8>(), "[0-9A-F]{20}").prop_map(|(id, serial_number)| {
+                Response::SerialNumber(SerialNumber { id, serial_number })
+            }),
+            (any::<u8>(), any::<u8>(), any::<i16>(), any::<i16>()).prop_map(
+                |(id, scene, level, duration)| Response::Scene(Scene {
+                    id,
+                    scene,
+                    level,
+                    duration,
+                })
+            ),
+            config().prop_map(Response::Config),
+            prop::collection::vec("[0-9A-Z]{1,4}", 5..=14).prop_map(Response::Unknown),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_response_round_trip(response in response()) {
+            let mut buf = BytesMut::new();
+            LumenCacheDeviceCodec.encode(response.clone(), &mut buf).unwrap();
+            prop_assert_eq!(LumenCacheCodec.decode(&mut buf).unwrap(), Some(response));
+            prop_assert!(buf.is_empty());
+        }
+    }
 }
@@ -3,12 +3,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use crate::protocol::codec::LumenCacheCodec;
-use anyhow::{Error, Result};
-use bytes::{BufMut, BytesMut};
-use tokio_util::codec::Encoder;
+use crate::protocol::codec::{LumenCacheCodec, LumenCacheDeviceCodec};
+use crate::protocol::decoder::{Config, MAX_FRAME_LENGTH};
+use anyhow::{anyhow, Error, Result};
+use bytes::{Buf, BufMut, BytesMut};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use tokio_util::codec::{Decoder, Encoder};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Commands {
     SetValue(SetValueCommand),
     GetValue(GetValueCommand),
@@ -21,20 +24,58 @@ pub enum Commands {
     GetConfig(GetConfigCommand),
     Hail,
     AssignId(AssignIdCommand),
+    SetConfig(SetConfigCommand),
 }
 
-#[derive(Clone, Debug)]
+/// Kind of a command without its arguments, which keys the settings of each command
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CommandKind {
+    SetValue,
+    GetValue,
+    SetScene,
+    ClearScene,
+    ClearScenes,
+    GetScenes,
+    ActivateScene,
+    DeactivateScene,
+    GetConfig,
+    Hail,
+    AssignId,
+    SetConfig,
+}
+
+impl Commands {
+    pub fn kind(&self) -> CommandKind {
+        match self {
+            Commands::SetValue(_) => CommandKind::SetValue,
+            Commands::GetValue(_) => CommandKind::GetValue,
+            Commands::SetScene(_) => CommandKind::SetScene,
+            Commands::ClearScene(_) => CommandKind::ClearScene,
+            Commands::ClearScenes(_) => CommandKind::ClearScenes,
+            Commands::GetScenes(_) => CommandKind::GetScenes,
+            Commands::ActivateScene(_) => CommandKind::ActivateScene,
+            Commands::DeactivateScene(_) => CommandKind::DeactivateScene,
+            Commands::GetConfig(_) => CommandKind::GetConfig,
+            Commands::Hail => CommandKind::Hail,
+            Commands::AssignId(_) => CommandKind::AssignId,
+            Commands::SetConfig(_) => CommandKind::SetConfig,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SetValueCommand {
     pub id: u8,
     pub value: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct GetValueCommand {
     pub id: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct SetSceneCommand {
     pub id: u8,
     pub scene: u8,
@@ -42,43 +83,132 @@ pub struct SetSceneCommand {
     pub level: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ClearSceneCommand {
     pub id: u8,
     pub scene: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ClearScenesCommand {
     pub id: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct GetScenesCommand {
     pub id: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ActivateSceneCommand {
     pub id: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct DeactivateSceneCommand {
     pub id: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct GetConfigCommand {
     pub id: u8,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct AssignIdCommand {
     pub id: u8,
     pub serial_number: String,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SetConfigCommand {
+    pub id: u8,
+    pub field: ConfigField,
+    pub value: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConfigField {
+    Mode,
+    DimmingCurve,
+    PwmFrequency,
+    MinimumOutputPwm,
+    MaximumOutputPwm,
+    ResumeLevel,
+    RampDuration,
+    MotionSensorEnable,
+    Mode6AlternateActions,
+    InvertedOutput,
+}
+
+impl ConfigField {
+    pub const ALL: [ConfigField; 10] = [
+        ConfigField::Mode,
+        ConfigField::DimmingCurve,
+        ConfigField::PwmFrequency,
+        ConfigField::MinimumOutputPwm,
+        ConfigField::MaximumOutputPwm,
+        ConfigField::ResumeLevel,
+        ConfigField::RampDuration,
+        ConfigField::MotionSensorEnable,
+        ConfigField::Mode6AlternateActions,
+        ConfigField::InvertedOutput,
+    ];
+
+    /// Position of the field in a `{...}` config frame
+    pub fn index(&self) -> u8 {
+        match self {
+            ConfigField::Mode => 5,
+            ConfigField::DimmingCurve => 6,
+            ConfigField::PwmFrequency => 7,
+            ConfigField::MinimumOutputPwm => 8,
+            ConfigField::MaximumOutputPwm => 9,
+            ConfigField::ResumeLevel => 10,
+            ConfigField::RampDuration => 11,
+            ConfigField::MotionSensorEnable => 12,
+            ConfigField::Mode6AlternateActions => 13,
+            ConfigField::InvertedOutput => 14,
+        }
+    }
+
+    pub fn from_index(index: u8) -> Option<ConfigField> {
+        ConfigField::ALL
+            .iter()
+            .find(|field| field.index() == index)
+            .copied()
+    }
+
+    pub fn read(&self, config: &Config) -> u8 {
+        match self {
+            ConfigField::Mode => config.mode.into(),
+            ConfigField::DimmingCurve => config.dimming_curve.into(),
+            ConfigField::PwmFrequency => config.pwm_frequency.into(),
+            ConfigField::MinimumOutputPwm => config.minimum_output_pwm,
+            ConfigField::MaximumOutputPwm => config.maximum_output_pwm,
+            ConfigField::ResumeLevel => config.resume_level,
+            ConfigField::RampDuration => config.ramp_duration,
+            ConfigField::MotionSensorEnable => config.motion_sensor_enable,
+            ConfigField::Mode6AlternateActions => config.mode_6_alternate_actions,
+            ConfigField::InvertedOutput => config.inverted_output,
+        }
+    }
+
+    pub fn write(&self, config: &mut Config, value: u8) {
+        match self {
+            ConfigField::Mode => config.mode = value.into(),
+            ConfigField::DimmingCurve => config.dimming_curve = value.into(),
+            ConfigField::PwmFrequency => config.pwm_frequency = value.into(),
+            ConfigField::MinimumOutputPwm => config.minimum_output_pwm = value,
+            ConfigField::MaximumOutputPwm => config.maximum_output_pwm = value,
+            ConfigField::ResumeLevel => config.resume_level = value,
+            ConfigField::RampDuration => config.ramp_duration = value,
+            ConfigField::MotionSensorEnable => config.motion_sensor_enable = value,
+            ConfigField::Mode6AlternateActions => config.mode_6_alternate_actions = value,
+            ConfigField::InvertedOutput => config.inverted_output = value,
+        }
+    }
+}
+
 impl Encoder<Commands> for LumenCacheCodec {
     type Error = Error;
 
@@ -119,15 +249,157 @@ impl Encoder<Commands> for LumenCacheCodec {
             Commands::AssignId(AssignIdCommand { id, serial_number }) => {
                 dst.put(format!("[{},{}]", 100_000 + id as u32, serial_number).as_bytes())
             }
+            Commands::SetConfig(SetConfigCommand { id, field, value }) => {
+                // Unverified: the protocol documentation doesn't describe writing a config
+                // field, so this frame is modelled on SetScene with the config frame index
+                dst.put(format!("[{},2{:02}{:03}]", id, field.index(), value).as_bytes())
+            }
         };
 
         Ok(())
     }
 }
 
+const BEGIN_COMMAND: u8 = b'[';
+const END_COMMAND: u8 = b']';
+
+impl Decoder for LumenCacheDeviceCodec {
+    type Item = Commands;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            match LumenCacheDeviceCodec::parse(buf) {
+                Some(Ok(command)) => return Ok(Some(command)),
+                Some(Err(err)) => log::warn!("Failed to parse command: {}", err),
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+impl LumenCacheDeviceCodec {
+    /// Parses the next complete `[...]` frame in `buf`.
+    ///
+    /// Frames which cannot be parsed are consumed and returned as error.
+    pub fn parse(buf: &mut BytesMut) -> Option<Result<Commands>> {
+        let begin = match buf.iter().position(|b| *b == BEGIN_COMMAND) {
+            Some(begin) => begin,
+            None => {
+                buf.clear();
+                return None;
+            }
+        };
+
+        buf.advance(begin);
+
+        let end = match buf
+            .iter()
+            .skip(1)
+            .position(|b| *b == BEGIN_COMMAND || *b == END_COMMAND)
+        {
+            Some(position) => position + 1,
+            None => {
+                if buf.len() > MAX_FRAME_LENGTH {
+                    buf.clear();
+                    return Some(Err(anyhow!("Frame exceeds {} bytes", MAX_FRAME_LENGTH)));
+                }
+
+                return None;
+            }
+        };
+
+        if buf[end] == BEGIN_COMMAND {
+            let bytes = buf.split_to(end);
+
+            return Some(Err(anyhow!(
+                "Frame {} was interrupted by the start of another frame",
+                String::from_utf8_lossy(&bytes[1..])
+            )));
+        }
+
+        let bytes = buf.split_to(end + 1);
+
+        Some(
+            std::str::from_utf8(&bytes[1..end])
+                .map_err(Error::from)
+                .and_then(LumenCacheDeviceCodec::parse_command),
+        )
+    }
+
+    pub fn parse_command(command: &str) -> Result<Commands> {
+        let parts: Vec<&str> = command.split(',').map(|s| s.trim()).collect();
+
+        if parts.len() != 2 {
+            return Err(anyhow!(
+                "Expected {:?} to have 2 parts but has {}",
+                parts,
+                parts.len()
+            ));
+        }
+
+        let address: u32 = parts[0].parse()?;
+
+        if address >= 100_000 {
+            return Ok(Commands::AssignId(AssignIdCommand {
+                id: u8::try_from(address - 100_000)?,
+                serial_number: parts[1].to_owned(),
+            }));
+        }
+
+        let value: u32 = parts[1].parse()?;
+
+        match (address, value) {
+            (253, 0) => Ok(Commands::Hail),
+            (254, 600..=855) => Ok(Commands::ActivateScene(ActivateSceneCommand {
+                id: (value - 600) as u8,
+            })),
+            (254, 900..=1155) => Ok(Commands::DeactivateScene(DeactivateSceneCommand {
+                id: (value - 900) as u8,
+            })),
+            (id, value) => {
+                let id = u8::try_from(id)?;
+
+                match value {
+                    0..=255 => Ok(Commands::SetValue(SetValueCommand {
+                        id,
+                        value: value as u8,
+                    })),
+                    256 => Ok(Commands::GetValue(GetValueCommand { id })),
+                    258 => Ok(Commands::GetConfig(GetConfigCommand { id })),
+                    700 => Ok(Commands::ClearScenes(ClearScenesCommand { id })),
+                    701..=799 => Ok(Commands::ClearScene(ClearSceneCommand {
+                        id,
+                        scene: (value - 700) as u8,
+                    })),
+                    10000 => Ok(Commands::GetScenes(GetScenesCommand { id })),
+                    200_000..=299_999 => {
+                        let index = (value / 1000 % 100) as u8;
+
+                        Ok(Commands::SetConfig(SetConfigCommand {
+                            id,
+                            field: ConfigField::from_index(index)
+                                .ok_or_else(|| anyhow!("Unknown config field {}", index))?,
+                            value: u8::try_from(value % 1000)?,
+                        }))
+                    }
+                    100_000_000..=199_999_999 => Ok(Commands::SetScene(SetSceneCommand {
+                        id,
+                        scene: (value / 1_000_000 % 100) as u8,
+                        ramp_duration: u8::try_from(value / 1000 % 1000)?,
+                        level: u8::try_from(value % 1000)?,
+                    })),
+                    value => Err(anyhow!("Unknown command {} for {}", value, id)),
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_set_value() {
@@ -161,4 +433,107 @@ mod tests {
             .unwrap();
         assert_eq!(buf.to_vec(), &b"[13,258]"[..]);
     }
+
+    #[test]
+    fn test_set_config() {
+        let mut encoder = LumenCacheCodec;
+        let mut buf = BytesMut::new();
+        encoder
+            .encode(
+                Commands::SetConfig(SetConfigCommand {
+                    id: 13,
+                    field: ConfigField::MinimumOutputPwm,
+                    value: 37,
+                }),
+                &mut buf,
+            )
+            .unwrap();
+        assert_eq!(buf.to_vec(), &b"[13,208037]"[..]);
+    }
+
+    #[test]
+    fn test_decode_command() {
+        let mut codec = LumenCacheDeviceCodec;
+        let mut buf =
+            BytesMut::from(&b"noise[13,37][13,[254,605][100007,0123456789ABCDEF0123]"[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Commands::SetValue(SetValueCommand { id: 13, value: 37 }))
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Commands::ActivateScene(ActivateSceneCommand { id: 5 }))
+        );
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Commands::AssignId(AssignIdCommand {
+                id: 7,
+                serial_number: String::from("0123456789ABCDEF0123")
+            }))
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(buf.to_vec(), &b""[..]);
+    }
+
+    #[test]
+    fn test_decode_unknown_command() {
+        let mut codec = LumenCacheDeviceCodec;
+        let mut buf = BytesMut::from(&b"[13,257][13,256]"[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(Commands::GetValue(GetValueCommand { id: 13 }))
+        );
+    }
+
+    fn config_field() -> impl Strategy<Value = ConfigField> {
+        prop::sample::select(ConfigField::ALL.to_vec())
+    }
+
+    fn command() -> impl Strategy<Value = Commands> {
+        // 253 and 254 are reserved for hailing and broadcasts
+        let id = 0..=252_u8;
+        let scene = 1..=64_u8;
+
+        prop_oneof![
+            (id.clone(), any::<u8>())
+                .prop_map(|(id, value)| Commands::SetValue(SetValueCommand { id, value })),
+            id.clone()
+                .prop_map(|id| Commands::GetValue(GetValueCommand { id })),
+            (id.clone(), scene.clone(), any::<u8>(), any::<u8>()).prop_map(
+                |(id, scene, ramp_duration, level)| Commands::SetScene(SetSceneCommand {
+                    id,
+                    scene,
+                    ramp_duration,
+                    level,
+                })
+            ),
+            (id.clone(), scene)
+                .prop_map(|(id, scene)| Commands::ClearScene(ClearSceneCommand { id, scene })),
+            id.clone()
+                .prop_map(|id| Commands::ClearScenes(ClearScenesCommand { id })),
+            id.clone()
+                .prop_map(|id| Commands::GetScenes(GetScenesCommand { id })),
+            any::<u8>().prop_map(|id| Commands::ActivateScene(ActivateSceneCommand { id })),
+            any::<u8>().prop_map(|id| Commands::DeactivateScene(DeactivateSceneCommand { id })),
+            id.clone()
+                .prop_map(|id| Commands::GetConfig(GetConfigCommand { id })),
+            Just(Commands::Hail),
+            (any::<u8>(), "[0-9A-F]{20}").prop_map(|(id, serial_number)| {
+                Commands::AssignId(AssignIdCommand { id, serial_number })
+            }),
+            (id, config_field(), any::<u8>()).prop_map(|(id, field, value)| {
+                Commands::SetConfig(SetConfigCommand { id, field, value })
+            }),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn test_command_round_trip(command in command()) {
+            let mut buf = BytesMut::new();
+            LumenCacheCodec.encode(command.clone(), &mut buf).unwrap();
+            prop_assert_eq!(LumenCacheDeviceCodec.decode(&mut buf).unwrap(), Some(command));
+            prop_assert!(buf.is_empty());
+        }
+    }
 }
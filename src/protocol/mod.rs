@@ -6,3 +6,4 @@
 pub mod codec;
 pub mod decoder;
 pub mod encoder;
+pub mod monitor;
@@ -0,0 +1,269 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::protocol::codec::{LumenCacheCodec, LumenCacheDeviceCodec, LumenCacheMonitorCodec};
+use crate::protocol::decoder::{Config, Response, Scene, Value, MAX_FRAME_LENGTH};
+use crate::protocol::encoder::{Commands, SetConfigCommand, SetSceneCommand, SetValueCommand};
+use anyhow::{Error, Result};
+use bytes::{Buf, BytesMut};
+use std::collections::HashMap;
+use tokio_util::codec::Decoder;
+
+/// A frame sent by any party on the bus
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Command(Commands),
+    Response(Response),
+}
+
+fn end_delimiter(begin: u8) -> Option<u8> {
+    match begin {
+        b'[' => Some(b']'),
+        b'(' => Some(b')'),
+        b'{' => Some(b'}'),
+        _ => None,
+    }
+}
+
+fn is_delimiter(b: u8) -> bool {
+    matches!(b, b'[' | b']' | b'(' | b')' | b'{' | b'}')
+}
+
+impl Decoder for LumenCacheMonitorCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            match LumenCacheMonitorCodec::parse(buf) {
+                Some(Some(frame)) => return Ok(Some(frame)),
+                Some(None) => continue,
+                None => return Ok(None),
+            }
+        }
+    }
+}
+
+impl LumenCacheMonitorCodec {
+    /// Parses the next complete frame of either direction in `buf`.
+    ///
+    /// Returns `Some(None)` if a broken command frame was skipped. Broken response frames are
+    /// returned as `Response::Malformed` like `LumenCacheCodec` does.
+    pub fn parse(buf: &mut BytesMut) -> Option<Option<Frame>> {
+        let begin = match buf.iter().position(|b| end_delimiter(*b).is_some()) {
+            Some(begin) => begin,
+            None => {
+                buf.clear();
+                return None;
+            }
+        };
+
+        buf.advance(begin);
+
+        let end = match buf.iter().skip(1).position(|b| is_delimiter(*b)) {
+            Some(position) => position + 1,
+            None => {
+                if buf.len() > MAX_FRAME_LENGTH {
+                    log::warn!("Skipping frame exceeding {} bytes", MAX_FRAME_LENGTH);
+                    buf.clear();
+                }
+
+                return None;
+            }
+        };
+
+        // Let the codec of the respective direction parse the frame on its own, so that the
+        // delimiters of the other direction are not mistaken for garbage
+        let interrupted = end_delimiter(buf[end]).is_some();
+        let mut frame = buf.split_to(if interrupted { end } else { end + 1 });
+
+        if frame[0] == b'[' {
+            if interrupted || frame[end] != b']' {
+                log::warn!(
+                    "Skipping broken command frame {}",
+                    String::from_utf8_lossy(&frame)
+                );
+                return Some(None);
+            }
+
+            return match LumenCacheDeviceCodec::parse(&mut frame)? {
+                Ok(command) => Some(Some(Frame::Command(command))),
+                Err(err) => {
+                    log::warn!("Failed to parse command: {}", err);
+                    Some(None)
+                }
+            };
+        }
+
+        if interrupted {
+            // Make the response codec see the start of the next frame
+            frame.extend_from_slice(b"(");
+        }
+
+        LumenCacheCodec::parse(&mut frame).map(|response| Some(Frame::Response(response)))
+    }
+}
+
+/// Returns whether `id` never belongs to a module, as it is unassigned or used for hailing and
+/// broadcasts
+pub fn is_reserved_id(id: u8) -> bool {
+    matches!(id, 0 | 253 | 254)
+}
+
+/// State of the modules as seen in the traffic of another controller
+#[derive(Default)]
+pub struct ObservedBus {
+    configs: HashMap<u8, Config>,
+}
+
+impl ObservedBus {
+    pub fn on_config(&mut self, config: &Config) {
+        if !is_reserved_id(config.id) {
+            self.configs.insert(config.id, config.clone());
+        }
+    }
+
+    /// Returns the response describing the effect of a command of the other controller
+    pub fn on_command(&mut self, command: Commands) -> Option<Response> {
+        match command {
+            Commands::SetValue(SetValueCommand { id, value }) if !is_reserved_id(id) => {
+                Some(Response::Value(Value { id, value }))
+            }
+            Commands::SetConfig(SetConfigCommand { id, field, value }) => {
+                // Only the config of a module whose config frame was seen is complete
+                let config = self.configs.get_mut(&id)?;
+                field.write(config, value);
+                Some(Response::Config(config.clone()))
+            }
+            Commands::SetScene(SetSceneCommand {
+                id,
+                scene,
+                ramp_duration,
+                level,
+            }) if !is_reserved_id(id) => Some(Response::Scene(Scene {
+                id,
+                scene,
+                level: level.into(),
+                duration: ramp_duration.into(),
+            })),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::encoder::{ConfigField, GetValueCommand};
+
+    fn decode_all(bytes: &[u8]) -> Vec<Frame> {
+        let mut buf = BytesMut::from(bytes);
+        let mut frames = Vec::new();
+
+        while let Some(frame) = LumenCacheMonitorCodec.decode(&mut buf).unwrap() {
+            frames.push(frame);
+        }
+
+        frames
+    }
+
+    #[test]
+    fn test_both_directions() {
+        assert_eq!(
+            decode_all(b"[5,42](5,42)xx[6,256] (6,0)"),
+            vec![
+                Frame::Command(Commands::SetValue(SetValueCommand { id: 5, value: 42 })),
+                Frame::Response(Response::Value(Value { id: 5, value: 42 })),
+                Frame::Command(Commands::GetValue(GetValueCommand { id: 6 })),
+                Frame::Response(Response::Value(Value { id: 6, value: 0 })),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_broken_frames() {
+        let frames = decode_all(b"[5,4(5,42)[5,42)(6,1[6,256]");
+
+        assert_eq!(
+            frames[0],
+            Frame::Response(Response::Value(Value { id: 5, value: 42 }))
+        );
+        assert!(matches!(
+            frames[1],
+            Frame::Response(Response::Malformed { .. })
+        ));
+        assert_eq!(
+            frames[2],
+            Frame::Command(Commands::GetValue(GetValueCommand { id: 6 }))
+        );
+        assert_eq!(frames.len(), 3);
+    }
+
+    #[test]
+    fn test_partial_frame() {
+        let mut buf = BytesMut::from(&b"(5,42)[5,"[..]);
+
+        assert!(matches!(
+            LumenCacheMonitorCodec.decode(&mut buf).unwrap(),
+            Some(Frame::Response(_))
+        ));
+        assert_eq!(LumenCacheMonitorCodec.decode(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(b"256]");
+        assert_eq!(
+            LumenCacheMonitorCodec.decode(&mut buf).unwrap(),
+            Some(Frame::Command(Commands::GetValue(GetValueCommand {
+                id: 5
+            })))
+        );
+    }
+
+    #[test]
+    fn test_observed_set_value() {
+        let mut bus = ObservedBus::default();
+        let frames = decode_all(b"[5,42][254,42]");
+
+        let responses: Vec<Option<Response>> = frames
+            .into_iter()
+            .map(|frame| match frame {
+                Frame::Command(command) => bus.on_command(command),
+                frame => panic!("Unexpected {:?}", frame),
+            })
+            .collect();
+        assert_eq!(
+            responses,
+            vec![Some(Response::Value(Value { id: 5, value: 42 })), None]
+        );
+    }
+
+    #[test]
+    fn test_observed_set_config() {
+        let mut bus = ObservedBus::default();
+        let set_mode = Commands::SetConfig(SetConfigCommand {
+            id: 5,
+            field: ConfigField::Mode,
+            value: 2,
+        });
+
+        // Without the config frame of the module the rest of its config is unknown
+        assert_eq!(bus.on_command(set_mode.clone()), None);
+
+        let config = match LumenCacheCodec::parse(&mut BytesMut::from(
+            &b"{5,7,3,123456.12,ABC,1,0,2,0,255,255,0,0,0,0}"[..],
+        )) {
+            Some(Response::Config(config)) => config,
+            response => panic!("Unexpected {:?}", response),
+        };
+        bus.on_config(&config);
+
+        match bus.on_command(set_mode) {
+            Some(Response::Config(updated)) => {
+                assert_eq!(u8::from(updated.mode), 2);
+                assert_eq!(updated.hardware_serial_number, "ABC");
+            }
+            response => panic!("Unexpected {:?}", response),
+        }
+    }
+}
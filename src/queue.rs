@@ -0,0 +1,376 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::request::{Priority, Request};
+use anyhow::{anyhow, Error, Result};
+use std::collections::VecDeque;
+use tokio::select;
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Instant};
+
+const QUEUE_SIZE: usize = 100;
+
+/// Number of interactive requests taken from the channel that wait to be coalesced
+const MAX_PENDING: usize = QUEUE_SIZE;
+
+/// Number of interactive requests after which a waiting background request is sent
+const MAX_INTERACTIVE_STREAK: usize = 8;
+
+/// A request handed out by a `RequestQueue`
+#[derive(Debug)]
+pub struct QueuedRequest {
+    pub request: Request,
+    /// Number of the attempt to send the command, starting at 1
+    pub attempt: u32,
+}
+
+/// A timed out request that is sent again once its backoff elapsed
+struct Retry {
+    not_before: Instant,
+    queued: QueuedRequest,
+}
+
+/// Sending side of a `RequestQueue`
+#[derive(Clone)]
+pub struct RequestSender {
+    interactive: mpsc::Sender<Request>,
+    background: mpsc::Sender<Request>,
+}
+
+/// Hands out interactive requests before background requests.
+///
+/// To keep discovery and polling going while a user is busy, a waiting background request
+/// is handed out after every `MAX_INTERACTIVE_STREAK` interactive requests.
+///
+/// A queued `SetValue` is replaced by a newer one for the same id, so that a burst of
+/// slider updates only sends the latest value. The newer one keeps its own position, so it
+/// never overtakes the interactive requests queued after the replaced one.
+///
+/// Retries are handed out before any other request once their backoff elapsed, so that the
+/// requests queued in the meantime are sent while waiting for them.
+pub struct RequestQueue {
+    interactive: mpsc::Receiver<Request>,
+    background: mpsc::Receiver<Request>,
+    pending: VecDeque<Request>,
+    retries: Vec<Retry>,
+    interactive_streak: usize,
+}
+
+impl RequestQueue {
+    pub fn new() -> (RequestSender, RequestQueue) {
+        let (interactive_tx, interactive_rx) = mpsc::channel(QUEUE_SIZE);
+        let (background_tx, background_rx) = mpsc::channel(QUEUE_SIZE);
+
+        let sender = RequestSender {
+            interactive: interactive_tx,
+            background: background_tx,
+        };
+
+        let queue = RequestQueue {
+            interactive: interactive_rx,
+            background: background_rx,
+            pending: VecDeque::new(),
+            retries: Vec::new(),
+            interactive_streak: 0,
+        };
+
+        (sender, queue)
+    }
+
+    /// Returns the next request, or `None` after all senders were dropped and no retry is left
+    pub async fn next(&mut self) -> Option<QueuedRequest> {
+        if let Some(queued) = self.take_retry(Instant::now()) {
+            return Some(queued);
+        }
+
+        while self.pending.len() < MAX_PENDING {
+            match self.interactive.try_recv() {
+                Ok(request) => self.push_interactive(request),
+                Err(_) => break,
+            }
+        }
+
+        if self.interactive_streak >= MAX_INTERACTIVE_STREAK {
+            if let Ok(request) = self.background.try_recv() {
+                log::debug!(
+                    "Sending background request after {} interactive ones",
+                    self.interactive_streak
+                );
+                self.interactive_streak = 0;
+                return Some(QueuedRequest::first(request));
+            }
+        }
+
+        if let Some(request) = self.pending.pop_front() {
+            self.interactive_streak += 1;
+            return Some(QueuedRequest::first(request));
+        }
+
+        let next_retry = self.retries.iter().map(|retry| retry.not_before).min();
+
+        let request = select! {
+            biased;
+            Some(request) = self.interactive.recv() => {
+                self.push_interactive(request);
+                self.pending.pop_front()?
+            }
+            Some(request) = self.background.recv() => request,
+            () = sleep_until(next_retry.unwrap_or_else(Instant::now)), if next_retry.is_some() => {
+                return self.take_retry(Instant::now());
+            }
+            else => return None,
+        };
+
+        match request.priority() {
+            Priority::Interactive => self.interactive_streak += 1,
+            Priority::Background => self.interactive_streak = 0,
+        }
+
+        Some(QueuedRequest::first(request))
+    }
+
+    /// Hands `request` out again as attempt `attempt`, but not before `not_before`
+    pub fn retry(&mut self, request: Request, attempt: u32, not_before: Instant) {
+        if let Some(newer) = set_value_id(&request).and_then(|id| {
+            self.pending
+                .iter_mut()
+                .find(|queued| set_value_id(queued) == Some(id))
+        }) {
+            log::debug!("Dropping the retry of {:?}", request.command());
+            newer.supersede(request);
+            return;
+        }
+
+        self.retries.push(Retry {
+            not_before,
+            queued: QueuedRequest { request, attempt },
+        });
+    }
+
+    /// Takes the retry that is due the longest at `now`
+    fn take_retry(&mut self, now: Instant) -> Option<QueuedRequest> {
+        let position = self
+            .retries
+            .iter()
+            .enumerate()
+            .filter(|(_, retry)| retry.not_before <= now)
+            .min_by_key(|(_, retry)| retry.not_before)
+            .map(|(position, _)| position)?;
+
+        Some(self.retries.remove(position).queued)
+    }
+
+    fn push_interactive(&mut self, mut request: Request) {
+        let id = match set_value_id(&request) {
+            Some(id) => id,
+            None => {
+                self.pending.push_back(request);
+                return;
+            }
+        };
+
+        // A retry waiting for its backoff would set the older value again after this one
+        let queued = self
+            .pending
+            .iter()
+            .position(|queued| set_value_id(queued) == Some(id))
+            .and_then(|position| self.pending.remove(position))
+            .or_else(|| {
+                self.retries
+                    .iter()
+                    .position(|retry| set_value_id(&retry.queued.request) == Some(id))
+                    .map(|position| self.retries.remove(position).queued.request)
+            });
+
+        if let Some(older) = queued {
+            log::debug!(
+                "Replacing {:?} with {:?}",
+                older.command(),
+                request.command()
+            );
+            request.supersede(older);
+        }
+
+        self.pending.push_back(request);
+    }
+}
+
+/// Returns the id of a `SetValue`, which a newer one for the same id replaces
+fn set_value_id(request: &Request) -> Option<u8> {
+    match request {
+        Request::SetValue { command, .. } => Some(command.id),
+        _ => None,
+    }
+}
+
+impl QueuedRequest {
+    fn first(request: Request) -> Self {
+        QueuedRequest {
+            request,
+            attempt: 1,
+        }
+    }
+}
+
+impl RequestSender {
+    pub async fn send(&self, request: Request) -> Result<(), Error> {
+        let tx = match request.priority() {
+            Priority::Interactive => &self.interactive,
+            Priority::Background => &self.background,
+        };
+
+        tx.send(request)
+            .await
+            .map_err(|_| anyhow!("Request queue was closed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::encoder::{Commands, GetConfigCommand, SetValueCommand};
+    use tokio::sync::oneshot;
+    use tokio::time::Duration;
+
+    fn set_value(id: u8) -> Request {
+        set_value_to(id, 1)
+    }
+
+    fn set_value_to(id: u8, value: u8) -> Request {
+        let (tx, _) = oneshot::channel();
+        let command = SetValueCommand { id, value };
+        Request::SetValue {
+            command,
+            tx,
+            superseded: Vec::new(),
+        }
+    }
+
+    fn get_config(id: u8) -> Request {
+        let (tx, _) = oneshot::channel();
+        let command = GetConfigCommand { id };
+        Request::GetConfig { command, tx }
+    }
+
+    async fn next_id(queue: &mut RequestQueue) -> u8 {
+        match queue.next().await.unwrap().request.command() {
+            Commands::SetValue(command) => command.id,
+            Commands::GetConfig(command) => command.id,
+            command => panic!("Unexpected {:?}", command),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interactive_first() {
+        let (sender, mut queue) = RequestQueue::new();
+
+        sender.send(get_config(1)).await.unwrap();
+        sender.send(get_config(2)).await.unwrap();
+        sender.send(set_value(3)).await.unwrap();
+
+        assert_eq!(next_id(&mut queue).await, 3);
+        assert_eq!(next_id(&mut queue).await, 1);
+        assert_eq!(next_id(&mut queue).await, 2);
+
+        drop(sender);
+        assert!(queue.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_no_starvation() {
+        let (sender, mut queue) = RequestQueue::new();
+
+        sender.send(get_config(100)).await.unwrap();
+        sender.send(get_config(101)).await.unwrap();
+
+        for id in 0..20 {
+            sender.send(set_value(id)).await.unwrap();
+        }
+
+        let mut ids = Vec::new();
+        for _ in 0..22 {
+            ids.push(next_id(&mut queue).await);
+        }
+
+        let expected: Vec<u8> = (0..8)
+            .chain(Some(100))
+            .chain(8..16)
+            .chain(Some(101))
+            .chain(16..20)
+            .collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_set_value() {
+        let (sender, mut queue) = RequestQueue::new();
+
+        sender.send(set_value_to(5, 1)).await.unwrap();
+        sender.send(set_value_to(6, 1)).await.unwrap();
+        sender.send(set_value_to(5, 2)).await.unwrap();
+        sender.send(set_value_to(5, 3)).await.unwrap();
+
+        let commands: Vec<Commands> = vec![
+            queue.next().await.unwrap().request.command(),
+            queue.next().await.unwrap().request.command(),
+        ];
+        assert_eq!(
+            commands,
+            vec![
+                Commands::SetValue(SetValueCommand { id: 6, value: 1 }),
+                Commands::SetValue(SetValueCommand { id: 5, value: 3 }),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bounded_pending() {
+        let (sender, mut queue) = RequestQueue::new();
+
+        // Fills the channel twice with distinct ids, so nothing is coalesced
+        for round in 0..2 {
+            for id in round * QUEUE_SIZE..(round + 1) * QUEUE_SIZE {
+                sender.send(set_value(id as u8)).await.unwrap();
+            }
+
+            queue.next().await.unwrap();
+            assert!(queue.pending.len() <= MAX_PENDING);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_not_before() {
+        let (sender, mut queue) = RequestQueue::new();
+
+        let start = Instant::now();
+        queue.retry(get_config(1), 2, start + Duration::from_millis(100));
+        sender.send(get_config(2)).await.unwrap();
+
+        // The other request is sent while the retry waits for its backoff
+        let queued = queue.next().await.unwrap();
+        assert_eq!(
+            queued.request.command(),
+            Commands::GetConfig(GetConfigCommand { id: 2 })
+        );
+        assert_eq!(queued.attempt, 1);
+
+        let queued = queue.next().await.unwrap();
+        assert_eq!(
+            queued.request.command(),
+            Commands::GetConfig(GetConfigCommand { id: 1 })
+        );
+        assert_eq!(queued.attempt, 2);
+        assert_eq!(start.elapsed(), Duration::from_millis(100));
+
+        // A due retry goes before the requests queued in the meantime
+        queue.retry(get_config(3), 2, Instant::now());
+        sender.send(set_value(4)).await.unwrap();
+        assert_eq!(next_id(&mut queue).await, 3);
+        assert_eq!(next_id(&mut queue).await, 4);
+
+        drop(sender);
+        assert!(queue.next().await.is_none());
+    }
+}
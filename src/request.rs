@@ -5,21 +5,22 @@
 
 use crate::protocol::decoder::{Config, Response, Scene, Value};
 use crate::protocol::encoder::{
-    ActivateSceneCommand, AssignIdCommand, ClearSceneCommand, ClearScenesCommand, Commands,
-    DeactivateSceneCommand, GetConfigCommand, GetScenesCommand, GetValueCommand, SetSceneCommand,
-    SetValueCommand,
+    ActivateSceneCommand, AssignIdCommand, ClearSceneCommand, ClearScenesCommand, CommandKind,
+    Commands, DeactivateSceneCommand, GetConfigCommand, GetScenesCommand, GetValueCommand,
+    SetConfigCommand, SetSceneCommand, SetValueCommand,
 };
 use std::fmt::Debug;
-use std::mem;
-use std::time::Instant;
 use tokio::sync::oneshot;
 use tokio::sync::oneshot::Sender;
+use tokio::time::Instant;
 
 #[derive(Debug)]
 pub enum Request {
     SetValue {
         command: SetValueCommand,
         tx: oneshot::Sender<RequestResponse<Value>>,
+        /// Callers of merged older requests, which receive the same response
+        superseded: Vec<oneshot::Sender<RequestResponse<Value>>>,
     },
     GetValue {
         command: GetValueCommand,
@@ -60,12 +61,52 @@ pub enum Request {
         command: AssignIdCommand,
         tx: oneshot::Sender<RequestResponse<Config>>,
     },
+    SetConfig {
+        command: SetConfigCommand,
+        tx: oneshot::Sender<RequestResponse<Config>>,
+    },
+}
+
+/// Order in which queued requests are sent to the bus
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Triggered by a user, who waits for the light to change
+    Interactive,
+    /// Discovery and polling, which can wait for interactive requests
+    Background,
 }
 
 impl Request {
+    pub fn priority(&self) -> Priority {
+        match self.command().kind() {
+            CommandKind::GetValue
+            | CommandKind::GetScenes
+            | CommandKind::GetConfig
+            | CommandKind::Hail
+            | CommandKind::AssignId => Priority::Background,
+            _ => Priority::Interactive,
+        }
+    }
+
+    /// Takes over the callers of an older `SetValue` for the same id, which is not sent anymore
+    pub fn supersede(&mut self, older: Request) {
+        if let (
+            Request::SetValue { superseded, .. },
+            Request::SetValue {
+                tx,
+                superseded: older_superseded,
+                ..
+            },
+        ) = (self, older)
+        {
+            superseded.extend(older_superseded);
+            superseded.push(tx);
+        }
+    }
+
     pub fn command(&self) -> Commands {
         match self {
-            Request::SetValue { command, tx: _ } => Commands::SetValue(command.to_owned()),
+            Request::SetValue { command, .. } => Commands::SetValue(command.to_owned()),
             Request::GetValue { command, tx: _ } => Commands::GetValue(command.to_owned()),
             Request::SetScene { command, tx: _ } => Commands::SetScene(command.to_owned()),
             Request::ClearScene { command, tx: _ } => Commands::ClearScene(command.to_owned()),
@@ -80,18 +121,80 @@ impl Request {
             Request::GetConfig { command, tx: _ } => Commands::GetConfig(command.to_owned()),
             Request::Hail { .. } => Commands::Hail,
             Request::AssignId { command, tx: _ } => Commands::AssignId(command.to_owned()),
+            Request::SetConfig { command, tx: _ } => Commands::SetConfig(command.to_owned()),
+        }
+    }
+
+    /// Command sent after `command` to read back its effect, as a module might only
+    /// acknowledge a write without reporting the new state
+    pub fn read_back(&self) -> Option<Commands> {
+        match self {
+            Request::SetConfig { command, .. } => {
+                Some(Commands::GetConfig(GetConfigCommand { id: command.id }))
+            }
+            _ => None,
         }
     }
+
+    /// Resolves the request with `RequestResponse::Timeout`
+    pub fn timeout(self) {
+        match self {
+            Request::SetValue { tx, superseded, .. } => {
+                for tx in superseded {
+                    log_send_error(tx.send(RequestResponse::Timeout));
+                }
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::GetValue { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::SetScene { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::ClearScene { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::ClearScenes { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::GetScenes { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::ActivateScene { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::DeactivateScene { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::GetConfig { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::Hail { tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::AssignId { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+            Request::SetConfig { command: _, tx } => {
+                log_send_error(tx.send(RequestResponse::Timeout));
+            }
+        };
+    }
 }
 
 #[derive(Debug)]
 pub enum RequestResponse<T> {
-    Response(T),
+    Response {
+        value: T,
+        /// Number of times the command was sent
+        attempts: u32,
+    },
     Timeout,
 }
 
 pub struct ResponseMatcher {
     request: Option<(Instant, Request, oneshot::Sender<()>)>,
+    attempts: u32,
     scenes: Vec<Scene>,
 }
 
@@ -99,20 +202,27 @@ impl ResponseMatcher {
     pub fn new() -> Self {
         ResponseMatcher {
             request: None,
+            attempts: 0,
             scenes: Vec::new(),
         }
     }
 
-    pub fn wait_for_response_to(&mut self, request: Request) -> oneshot::Receiver<()> {
+    /// Waits for the response to the `attempts`th sending of the request
+    pub fn wait_for_response_to(
+        &mut self,
+        request: Request,
+        attempts: u32,
+    ) -> oneshot::Receiver<()> {
         log::trace!("Waiting for response to {:?}", request);
         let (tx, rx) = oneshot::channel();
         self.request = Some((Instant::now(), request, tx));
+        self.attempts = attempts;
 
         rx
     }
 
     pub async fn handle_response(&mut self, response: &Response) {
-        let request = mem::replace(&mut self.request, None);
+        let request = self.request.take();
 
         if let Some((instant, request, tx)) = request {
             self.request = self.handle_request(instant, request, response, tx);
@@ -129,18 +239,29 @@ impl ResponseMatcher {
     ) -> Option<(Instant, Request, oneshot::Sender<()>)> {
         log::trace!("Matching {:?} {:?}", request, response);
         let command = request.command();
+        let attempts = self.attempts;
 
         match (request, response) {
             (
                 Request::SetValue {
                     command: SetValueCommand { id, .. },
                     tx,
+                    superseded,
                 },
                 Response::Value(value),
             ) if id == value.id => {
                 log_response_time(instant, &command);
 
-                log_send_error(tx.send(RequestResponse::Response(value.to_owned())));
+                for tx in superseded {
+                    log_send_error(tx.send(RequestResponse::Response {
+                        value: value.to_owned(),
+                        attempts,
+                    }));
+                }
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: value.to_owned(),
+                    attempts,
+                }));
                 log_send_error(tx_complete.send(()));
                 None
             }
@@ -153,7 +274,10 @@ impl ResponseMatcher {
             ) if id == value.id => {
                 log_response_time(instant, &command);
 
-                log_send_error(tx.send(RequestResponse::Response(value.to_owned())));
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: value.to_owned(),
+                    attempts,
+                }));
                 log_send_error(tx_complete.send(()));
                 None
             }
@@ -166,7 +290,10 @@ impl ResponseMatcher {
             ) if id == response_scene.id && scene == response_scene.scene => {
                 log_response_time(instant, &command);
 
-                log_send_error(tx.send(RequestResponse::Response(response_scene.to_owned())));
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: response_scene.to_owned(),
+                    attempts,
+                }));
                 log_send_error(tx_complete.send(()));
                 None
             }
@@ -179,7 +306,10 @@ impl ResponseMatcher {
             ) if id == response_scene.id && scene == response_scene.scene => {
                 log_response_time(instant, &command);
 
-                log_send_error(tx.send(RequestResponse::Response(response_scene.to_owned())));
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: response_scene.to_owned(),
+                    attempts,
+                }));
                 log_send_error(tx_complete.send(()));
                 None
             }
@@ -203,16 +333,20 @@ impl ResponseMatcher {
                         tx_complete,
                     ))
                 }
-                64 => {
+                64 if self.has_scenes_before(64) => {
                     log_response_time(instant, &command);
 
+                    self.add_scene(scene);
                     let scenes = std::mem::take(&mut self.scenes);
-                    log_send_error(tx.send(RequestResponse::Response(scenes)));
+                    log_send_error(tx.send(RequestResponse::Response {
+                        value: scenes,
+                        attempts,
+                    }));
                     log_send_error(tx_complete.send(()));
                     None
                 }
                 _ => {
-                    self.scenes.push(scene.to_owned());
+                    self.add_scene(scene);
 
                     Some((
                         instant,
@@ -244,16 +378,20 @@ impl ResponseMatcher {
                         tx_complete,
                     ))
                 }
-                64 => {
+                64 if self.has_scenes_before(64) => {
                     log_response_time(instant, &command);
 
+                    self.add_scene(scene);
                     let scenes = std::mem::take(&mut self.scenes);
-                    log_send_error(tx.send(RequestResponse::Response(scenes)));
+                    log_send_error(tx.send(RequestResponse::Response {
+                        value: scenes,
+                        attempts,
+                    }));
                     log_send_error(tx_complete.send(()));
                     None
                 }
                 _ => {
-                    self.scenes.push(scene.to_owned());
+                    self.add_scene(scene);
 
                     Some((
                         instant,
@@ -274,7 +412,10 @@ impl ResponseMatcher {
             ) => {
                 log_response_time(instant, &command);
 
-                log_send_error(tx.send(RequestResponse::Response(())));
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: (),
+                    attempts,
+                }));
                 log_send_error(tx_complete.send(()));
                 None
             }
@@ -287,7 +428,10 @@ impl ResponseMatcher {
             ) => {
                 log_response_time(instant, &command);
 
-                log_send_error(tx.send(RequestResponse::Response(())));
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: (),
+                    attempts,
+                }));
                 log_send_error(tx_complete.send(()));
                 None
             }
@@ -300,14 +444,20 @@ impl ResponseMatcher {
             ) if id == config.id => {
                 log_response_time(instant, &command);
 
-                log_send_error(tx.send(RequestResponse::Response(config.to_owned())));
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: config.to_owned(),
+                    attempts,
+                }));
                 log_send_error(tx_complete.send(()));
                 None
             }
             (Request::Hail { tx }, Response::Config(config)) => {
                 log_response_time(instant, &command);
 
-                log_send_error(tx.send(RequestResponse::Response(config.to_owned())));
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: config.to_owned(),
+                    attempts,
+                }));
                 log_send_error(tx_complete.send(()));
                 None
             }
@@ -320,7 +470,37 @@ impl ResponseMatcher {
             ) if id == config.id => {
                 log_response_time(instant, &command);
 
-                log_send_error(tx.send(RequestResponse::Response(config.to_owned())));
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: config.to_owned(),
+                    attempts,
+                }));
+                log_send_error(tx_complete.send(()));
+                None
+            }
+            (
+                Request::SetConfig {
+                    command: SetConfigCommand { id, field, value },
+                    tx,
+                },
+                Response::Config(config),
+            ) if id == config.id => {
+                // A module may clamp or round the value, which sending it again won't change
+                if field.read(config) != value {
+                    log::warn!(
+                        "Read back {:?} of {} as {} instead of {}",
+                        field,
+                        id,
+                        field.read(config),
+                        value
+                    );
+                }
+
+                log_response_time(instant, &command);
+
+                log_send_error(tx.send(RequestResponse::Response {
+                    value: config.to_owned(),
+                    attempts,
+                }));
                 log_send_error(tx_complete.send(()));
                 None
             }
@@ -328,47 +508,30 @@ impl ResponseMatcher {
         }
     }
 
-    pub fn timeout(&mut self) {
-        let request = mem::replace(&mut self.request, None);
+    /// Collects a listed scene, replacing a duplicated frame of the same scene
+    fn add_scene(&mut self, scene: &Scene) {
+        match self
+            .scenes
+            .iter_mut()
+            .find(|known| known.scene == scene.scene)
+        {
+            Some(known) => *known = scene.to_owned(),
+            None => self.scenes.push(scene.to_owned()),
+        }
+    }
+
+    /// Whether all scenes numbered below `last` were listed, a listing with a dropped frame
+    /// keeps waiting until it is requested again
+    fn has_scenes_before(&self, last: u8) -> bool {
+        (1..last).all(|nr| self.scenes.iter().any(|scene| scene.scene == nr))
+    }
+
+    /// Stops waiting and returns the unanswered request, so that it can be sent again
+    pub fn take_request(&mut self) -> Option<Request> {
+        let request = self.request.take();
         log::trace!("Timeout {:?}", request);
 
-        if let Some((_, request, _)) = request {
-            match request {
-                Request::SetValue { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::GetValue { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::SetScene { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::ClearScene { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::ClearScenes { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::GetScenes { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::ActivateScene { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::DeactivateScene { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::GetConfig { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::Hail { tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-                Request::AssignId { command: _, tx } => {
-                    log_send_error(tx.send(RequestResponse::Timeout));
-                }
-            };
-        }
+        request.map(|(_, request, _)| request)
     }
 }
 
@@ -385,3 +548,174 @@ fn log_send_error<T: Debug>(result: Result<(), T>) {
         log::warn!("Receiver was dropped before {:?} could be send", value);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codec::LumenCacheCodec;
+    use crate::protocol::encoder::ConfigField;
+    use bytes::BytesMut;
+    use tokio::sync::oneshot::error::TryRecvError;
+
+    fn config(mode: u8) -> Response {
+        let frame = format!("{{5,7,3,123456.12,ABC,{},0,2,0,255,255,0,0,0,0}}", mode);
+        LumenCacheCodec::parse(&mut BytesMut::from(frame.as_bytes())).unwrap()
+    }
+
+    fn value(id: u8, value: u8) -> Response {
+        Response::Value(Value { id, value })
+    }
+
+    fn scene(id: u8, scene: u8) -> Response {
+        Response::Scene(Scene {
+            id,
+            scene,
+            level: -1,
+            duration: -1,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_unmatched_response() {
+        let mut matcher = ResponseMatcher::new();
+        let (tx, mut rx) = oneshot::channel();
+        let command = GetValueCommand { id: 5 };
+        let mut complete = matcher.wait_for_response_to(Request::GetValue { command, tx }, 1);
+
+        matcher.handle_response(&value(6, 1)).await;
+        matcher.handle_response(&config(1)).await;
+        assert_eq!(complete.try_recv(), Err(TryRecvError::Empty));
+
+        matcher.handle_response(&value(5, 42)).await;
+        assert!(complete.try_recv().is_ok());
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(RequestResponse::Response {
+                value: Value { id: 5, value: 42 },
+                attempts: 1,
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_config_read_back() {
+        let mut matcher = ResponseMatcher::new();
+        let (tx, mut rx) = oneshot::channel();
+        let command = SetConfigCommand {
+            id: 5,
+            field: ConfigField::Mode,
+            value: 2,
+        };
+        let mut complete = matcher.wait_for_response_to(Request::SetConfig { command, tx }, 1);
+
+        matcher.handle_response(&value(5, 2)).await;
+        assert_eq!(complete.try_recv(), Err(TryRecvError::Empty));
+
+        matcher.handle_response(&config(2)).await;
+        assert!(complete.try_recv().is_ok());
+        assert!(matches!(
+            rx.try_recv(),
+            Ok(RequestResponse::Response { attempts: 1, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_set_config_clamped() {
+        let mut matcher = ResponseMatcher::new();
+        let (tx, mut rx) = oneshot::channel();
+        let command = SetConfigCommand {
+            id: 5,
+            field: ConfigField::Mode,
+            value: 200,
+        };
+        let mut complete = matcher.wait_for_response_to(Request::SetConfig { command, tx }, 1);
+
+        // The module kept a different value, which is returned instead of waiting for a timeout
+        matcher.handle_response(&config(1)).await;
+        assert!(complete.try_recv().is_ok());
+        match rx.try_recv() {
+            Ok(RequestResponse::Response { value, attempts: 1 }) => {
+                assert_eq!(ConfigField::Mode.read(&value), 1)
+            }
+            response => panic!("Unexpected {:?}", response),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let mut matcher = ResponseMatcher::new();
+        assert!(matcher.take_request().is_none());
+
+        let (tx, mut rx) = oneshot::channel();
+        let command = GetValueCommand { id: 5 };
+        let _complete = matcher.wait_for_response_to(Request::GetValue { command, tx }, 1);
+        matcher.take_request().unwrap().timeout();
+        assert!(matches!(rx.try_recv(), Ok(RequestResponse::Timeout)));
+
+        // Responses after the timeout are not matched anymore
+        matcher.handle_response(&value(5, 42)).await;
+        assert!(matcher.request.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_last_scene_is_kept() {
+        let mut matcher = ResponseMatcher::new();
+        let (tx, mut rx) = oneshot::channel();
+        let command = GetScenesCommand { id: 5 };
+        let _complete = matcher.wait_for_response_to(Request::GetScenes { command, tx }, 1);
+
+        for nr in 1..=64 {
+            matcher.handle_response(&scene(5, nr)).await;
+        }
+
+        // The 64th scene completes the listing and must be part of it
+        match rx.try_recv() {
+            Ok(RequestResponse::Response {
+                value: scenes,
+                attempts: 1,
+            }) => {
+                assert_eq!(scenes.len(), 64);
+                assert_eq!(scenes.last().map(|scene| scene.scene), Some(64));
+            }
+            response => panic!("Unexpected {:?}", response),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplicated_scene() {
+        let mut matcher = ResponseMatcher::new();
+        let (tx, mut rx) = oneshot::channel();
+        let command = GetScenesCommand { id: 5 };
+        let _complete = matcher.wait_for_response_to(Request::GetScenes { command, tx }, 1);
+
+        for nr in 1..=64 {
+            matcher.handle_response(&scene(5, nr)).await;
+
+            if nr == 10 {
+                matcher.handle_response(&scene(5, nr)).await;
+            }
+        }
+
+        match rx.try_recv() {
+            Ok(RequestResponse::Response {
+                value: scenes,
+                attempts: 1,
+            }) => assert_eq!(scenes.len(), 64),
+            response => panic!("Unexpected {:?}", response),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dropped_scene() {
+        let mut matcher = ResponseMatcher::new();
+        let (tx, mut rx) = oneshot::channel();
+        let command = GetScenesCommand { id: 5 };
+        let mut complete = matcher.wait_for_response_to(Request::GetScenes { command, tx }, 1);
+
+        for nr in (1..=64).filter(|nr| *nr != 10) {
+            matcher.handle_response(&scene(5, nr)).await;
+        }
+        assert_eq!(complete.try_recv(), Err(TryRecvError::Empty));
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Empty)));
+    }
+}
@@ -31,6 +31,10 @@ impl DeviceStructure for LumenCacheScene {
     }
 
     fn actions(&self) -> Actions {
+        if self.controller.is_passive() {
+            return Vec::new();
+        }
+
         vec![
             Box::new(ActivateAction::new(self.id, self.controller.clone())),
             Box::new(DeactivateAction::new(self.id, self.controller.clone())),
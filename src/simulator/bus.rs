@@ -0,0 +1,377 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::protocol::decoder::{
+    Config, DimmingCurve, HardwareType, Mode, PwmFrequency, Response, Scene, Value,
+};
+use crate::protocol::encoder::{
+    ActivateSceneCommand, AssignIdCommand, ClearSceneCommand, ClearScenesCommand, Commands,
+    DeactivateSceneCommand, GetConfigCommand, GetScenesCommand, GetValueCommand, SetConfigCommand,
+    SetSceneCommand, SetValueCommand,
+};
+
+pub const SCENE_COUNT: u8 = 64;
+
+/// Id reported by modules which have not been assigned an id yet
+pub const UNASSIGNED_ID: u8 = 0;
+
+/// Level and ramp duration reported for empty scene slots
+const EMPTY_SCENE: i16 = -1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SceneSlot {
+    pub level: u8,
+    pub ramp_duration: u8,
+}
+
+/// A single dimmer module on the simulated bus
+#[derive(Clone, Debug)]
+pub struct SimulatedModule {
+    pub config: Config,
+    pub value: u8,
+    pub scenes: Vec<Option<SceneSlot>>,
+    /// Value before the last scene activation, restored by the deactivation
+    resume_value: Option<u8>,
+}
+
+impl SimulatedModule {
+    pub fn new(serial_number: String, id: Option<u8>) -> Self {
+        SimulatedModule {
+            config: Config {
+                id: id.unwrap_or(UNASSIGNED_ID),
+                hardware_type: HardwareType::LedDriver,
+                hardware_version: 3,
+                firmware_version: String::from("123456.12"),
+                hardware_serial_number: serial_number,
+                mode: Mode::Dimmer,
+                dimming_curve: DimmingCurve::Linear,
+                pwm_frequency: PwmFrequency::Hz1000,
+                minimum_output_pwm: 0,
+                maximum_output_pwm: 255,
+                resume_level: 255,
+                ramp_duration: 0,
+                motion_sensor_enable: 0,
+                mode_6_alternate_actions: 0,
+                inverted_output: 0,
+            },
+            value: 0,
+            scenes: vec![None; SCENE_COUNT as usize],
+            resume_value: None,
+        }
+    }
+
+    pub fn id(&self) -> Option<u8> {
+        match self.config.id {
+            UNASSIGNED_ID => None,
+            id => Some(id),
+        }
+    }
+
+    fn value(&self) -> Response {
+        Response::Value(Value {
+            id: self.config.id,
+            value: self.value,
+        })
+    }
+
+    fn config(&self) -> Response {
+        Response::Config(self.config.clone())
+    }
+
+    fn scene(&self, scene: u8) -> Response {
+        let (level, duration) = match self.scenes[scene as usize - 1] {
+            Some(slot) => (slot.level as i16, slot.ramp_duration as i16),
+            None => (EMPTY_SCENE, EMPTY_SCENE),
+        };
+
+        Response::Scene(Scene {
+            id: self.config.id,
+            scene,
+            level,
+            duration,
+        })
+    }
+
+    fn scenes(&self) -> Vec<Response> {
+        (1..=SCENE_COUNT).map(|scene| self.scene(scene)).collect()
+    }
+}
+
+fn is_scene(scene: u8) -> bool {
+    (1..=SCENE_COUNT).contains(&scene)
+}
+
+/// The state of all modules on a simulated bus
+#[derive(Clone, Debug, Default)]
+pub struct Bus {
+    pub modules: Vec<SimulatedModule>,
+}
+
+impl Bus {
+    pub fn new(modules: Vec<SimulatedModule>) -> Self {
+        Bus { modules }
+    }
+
+    /// Creates `count` modules which still have to be discovered with the hail flow
+    pub fn unassigned(count: usize) -> Self {
+        Bus::new(
+            (1..=count)
+                .map(|index| SimulatedModule::new(format!("{:020X}", index), None))
+                .collect(),
+        )
+    }
+
+    /// Applies `command` to all addressed modules and returns their replies in bus order
+    pub fn handle(&mut self, command: Commands) -> Vec<Response> {
+        match command {
+            Commands::Hail => self
+                .modules
+                .iter()
+                .find(|module| module.id().is_none())
+                .map(SimulatedModule::config)
+                .into_iter()
+                .collect(),
+            Commands::AssignId(AssignIdCommand { id, serial_number }) => self
+                .modules
+                .iter_mut()
+                .filter(|module| module.config.hardware_serial_number == serial_number)
+                .map(|module| {
+                    module.config.id = id;
+                    module.config()
+                })
+                .collect(),
+            Commands::ActivateScene(ActivateSceneCommand { id: scene }) if is_scene(scene) => self
+                .modules
+                .iter_mut()
+                .filter(|module| module.id().is_some())
+                .filter_map(|module| {
+                    let slot = module.scenes[scene as usize - 1]?;
+                    module.resume_value.get_or_insert(module.value);
+                    module.value = slot.level;
+                    Some(module.value())
+                })
+                .collect(),
+            Commands::DeactivateScene(DeactivateSceneCommand { id: scene }) if is_scene(scene) => {
+                self.modules
+                    .iter_mut()
+                    .filter(|module| module.id().is_some())
+                    .filter(|module| module.scenes[scene as usize - 1].is_some())
+                    .map(|module| {
+                        module.value = module.resume_value.take().unwrap_or(0);
+                        module.value()
+                    })
+                    .collect()
+            }
+            command => {
+                let id = match addressed_id(&command) {
+                    Some(id) => id,
+                    None => return Vec::new(),
+                };
+
+                self.modules
+                    .iter_mut()
+                    .filter(|module| module.id() == Some(id))
+                    .flat_map(|module| Bus::handle_addressed(module, &command))
+                    .collect()
+            }
+        }
+    }
+
+    fn handle_addressed(module: &mut SimulatedModule, command: &Commands) -> Vec<Response> {
+        match *command {
+            Commands::SetValue(SetValueCommand { value, .. }) => {
+                module.value = value;
+                module.resume_value = None;
+                vec![module.value()]
+            }
+            Commands::GetValue(_) => vec![module.value()],
+            Commands::SetScene(SetSceneCommand {
+                scene,
+                ramp_duration,
+                level,
+                ..
+            }) if is_scene(scene) => {
+                module.scenes[scene as usize - 1] = Some(SceneSlot {
+                    level,
+                    ramp_duration,
+                });
+                vec![module.scene(scene)]
+            }
+            Commands::ClearScene(ClearSceneCommand { scene, .. }) if is_scene(scene) => {
+                module.scenes[scene as usize - 1] = None;
+                vec![module.scene(scene)]
+            }
+            Commands::ClearScenes(_) => {
+                module.scenes = vec![None; SCENE_COUNT as usize];
+                module.scenes()
+            }
+            Commands::GetScenes(_) => module.scenes(),
+            Commands::GetConfig(_) => vec![module.config()],
+            Commands::SetConfig(SetConfigCommand { field, value, .. }) => {
+                field.write(&mut module.config, value);
+                vec![module.config()]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn addressed_id(command: &Commands) -> Option<u8> {
+    match command {
+        Commands::SetValue(SetValueCommand { id, .. })
+        | Commands::GetValue(GetValueCommand { id })
+        | Commands::SetScene(SetSceneCommand { id, .. })
+        | Commands::ClearScene(ClearSceneCommand { id, .. })
+        | Commands::ClearScenes(ClearScenesCommand { id })
+        | Commands::GetScenes(GetScenesCommand { id })
+        | Commands::GetConfig(GetConfigCommand { id })
+        | Commands::SetConfig(SetConfigCommand { id, .. }) => Some(*id),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::encoder::ConfigField;
+
+    fn module(bus: &Bus, id: u8) -> &SimulatedModule {
+        bus.modules
+            .iter()
+            .find(|module| module.id() == Some(id))
+            .unwrap()
+    }
+
+    fn bus() -> Bus {
+        Bus::new(vec![
+            SimulatedModule::new(String::from("A"), Some(5)),
+            SimulatedModule::new(String::from("B"), Some(6)),
+            SimulatedModule::new(String::from("C"), None),
+        ])
+    }
+
+    fn value(id: u8, value: u8) -> Response {
+        Response::Value(Value { id, value })
+    }
+
+    #[test]
+    fn test_value() {
+        let mut bus = bus();
+
+        assert_eq!(
+            bus.handle(Commands::SetValue(SetValueCommand { id: 5, value: 42 })),
+            vec![value(5, 42)]
+        );
+        assert_eq!(
+            bus.handle(Commands::GetValue(GetValueCommand { id: 5 })),
+            vec![value(5, 42)]
+        );
+        assert_eq!(
+            bus.handle(Commands::GetValue(GetValueCommand { id: 6 })),
+            vec![value(6, 0)]
+        );
+        assert!(bus
+            .handle(Commands::GetValue(GetValueCommand { id: 7 }))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_hail_and_assign_id() {
+        let mut bus = bus();
+
+        let responses = bus.handle(Commands::Hail);
+        assert!(matches!(
+            &responses[..],
+            [Response::Config(Config { id: UNASSIGNED_ID, hardware_serial_number, .. })]
+                if hardware_serial_number == "C"
+        ));
+
+        let responses = bus.handle(Commands::AssignId(AssignIdCommand {
+            id: 7,
+            serial_number: String::from("C"),
+        }));
+        assert!(matches!(
+            &responses[..],
+            [Response::Config(Config { id: 7, .. })]
+        ));
+
+        assert!(bus.handle(Commands::Hail).is_empty());
+        assert_eq!(module(&bus, 7).config.hardware_serial_number, "C");
+    }
+
+    #[test]
+    fn test_scenes() {
+        let mut bus = bus();
+
+        assert_eq!(
+            bus.handle(Commands::SetScene(SetSceneCommand {
+                id: 5,
+                scene: 3,
+                ramp_duration: 10,
+                level: 200,
+            })),
+            vec![Response::Scene(Scene {
+                id: 5,
+                scene: 3,
+                level: 200,
+                duration: 10,
+            })]
+        );
+
+        let scenes = bus.handle(Commands::GetScenes(GetScenesCommand { id: 5 }));
+        assert_eq!(scenes.len(), SCENE_COUNT as usize);
+        assert!(matches!(
+            scenes[2],
+            Response::Scene(Scene {
+                scene: 3,
+                level: 200,
+                ..
+            })
+        ));
+        assert!(matches!(
+            scenes[63],
+            Response::Scene(Scene {
+                scene: 64,
+                level: EMPTY_SCENE,
+                ..
+            })
+        ));
+
+        bus.handle(Commands::SetValue(SetValueCommand { id: 5, value: 17 }));
+        assert_eq!(
+            bus.handle(Commands::ActivateScene(ActivateSceneCommand { id: 3 })),
+            vec![value(5, 200)]
+        );
+        assert_eq!(
+            bus.handle(Commands::DeactivateScene(DeactivateSceneCommand { id: 3 })),
+            vec![value(5, 17)]
+        );
+        assert!(bus
+            .handle(Commands::ActivateScene(ActivateSceneCommand { id: 4 }))
+            .is_empty());
+
+        bus.handle(Commands::ClearScene(ClearSceneCommand { id: 5, scene: 3 }));
+        assert!(module(&bus, 5).scenes.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_set_config() {
+        let mut bus = bus();
+
+        let responses = bus.handle(Commands::SetConfig(SetConfigCommand {
+            id: 6,
+            field: ConfigField::Mode,
+            value: 2,
+        }));
+        assert!(matches!(
+            &responses[..],
+            [Response::Config(Config {
+                id: 6,
+                mode: Mode::Switch,
+                ..
+            })]
+        ));
+    }
+}
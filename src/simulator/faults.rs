@@ -0,0 +1,214 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::config::Faults;
+use tokio::time::Duration;
+
+const MAX_GARBAGE_LENGTH: usize = 8;
+
+/// Small deterministic generator (xorshift64*), so that a seed always reproduces the same faults
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Rng {
+            state: (seed ^ 0x9E37_79B9_7F4A_7C15).max(1),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0_f64 && (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64 <= probability
+    }
+
+    fn below(&mut self, bound: u64) -> u64 {
+        match bound {
+            0 => 0,
+            bound => self.next_u64() % bound,
+        }
+    }
+
+    fn byte(&mut self) -> u8 {
+        self.next_u64() as u8
+    }
+}
+
+/// A chunk of bytes to write to the bus after `delay`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+    pub delay: Duration,
+    pub bytes: Vec<u8>,
+}
+
+/// Applies randomly chosen faults to the encoded reply frames of a simulated bus
+pub struct FaultInjector {
+    faults: Faults,
+    rng: Rng,
+    held_back: Option<Vec<u8>>,
+}
+
+impl FaultInjector {
+    pub fn new(faults: Faults) -> Self {
+        let rng = Rng::new(faults.seed);
+
+        FaultInjector {
+            faults,
+            rng,
+            held_back: None,
+        }
+    }
+
+    /// Turns the reply frames to a single command into the chunks written to the bus.
+    ///
+    /// A frame held back for reordering is sent after the next frame, which may belong to the
+    /// reply to a later command.
+    pub fn inject(&mut self, frames: Vec<Vec<u8>>) -> Vec<Chunk> {
+        let mut chunks = Vec::new();
+
+        for mut frame in frames {
+            if self.rng.chance(self.faults.drop) {
+                log::debug!("Dropping {}", String::from_utf8_lossy(&frame));
+                continue;
+            }
+
+            if !frame.is_empty() && self.rng.chance(self.faults.corrupt) {
+                let position = self.rng.below(frame.len() as u64) as usize;
+                frame[position] = self.rng.byte();
+            }
+
+            if self.held_back.is_none() && self.rng.chance(self.faults.reorder) {
+                self.held_back = Some(frame);
+                continue;
+            }
+
+            let copies = if self.rng.chance(self.faults.duplicate) {
+                2
+            } else {
+                1
+            };
+
+            for _ in 0..copies {
+                chunks.push(self.chunk(frame.clone()));
+            }
+
+            if let Some(held_back) = self.held_back.take() {
+                chunks.push(self.chunk(held_back));
+            }
+        }
+
+        chunks
+    }
+
+    fn chunk(&mut self, frame: Vec<u8>) -> Chunk {
+        let delay = if self.rng.chance(self.faults.delay) {
+            Duration::from_millis(self.rng.below(self.faults.max_delay_ms + 1))
+        } else {
+            Duration::from_millis(0)
+        };
+
+        let mut bytes = Vec::new();
+
+        if self.rng.chance(self.faults.garbage) {
+            let length = 1 + self.rng.below(MAX_GARBAGE_LENGTH as u64);
+            bytes.extend((0..length).map(|_| self.rng.byte()));
+        }
+
+        bytes.extend(frame);
+
+        Chunk { delay, bytes }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frames() -> Vec<Vec<u8>> {
+        (1..=64)
+            .map(|scene| format!("{{5,{},-1,-1}}", scene).into_bytes())
+            .collect()
+    }
+
+    fn bytes(chunks: &[Chunk]) -> Vec<u8> {
+        chunks
+            .iter()
+            .flat_map(|chunk| chunk.bytes.clone())
+            .collect()
+    }
+
+    fn noisy(seed: u64) -> Faults {
+        Faults {
+            seed,
+            drop: 0.1,
+            delay: 0.1,
+            max_delay_ms: 20,
+            duplicate: 0.1,
+            corrupt: 0.1,
+            reorder: 0.1,
+            garbage: 0.1,
+        }
+    }
+
+    #[test]
+    fn test_no_faults() {
+        let chunks = FaultInjector::new(Faults::default()).inject(frames());
+
+        assert_eq!(chunks.len(), 64);
+        assert!(chunks.iter().all(|chunk| chunk.delay.as_millis() == 0));
+        assert_eq!(bytes(&chunks), frames().concat());
+    }
+
+    #[test]
+    fn test_seed() {
+        let first = FaultInjector::new(noisy(42)).inject(frames());
+        let second = FaultInjector::new(noisy(42)).inject(frames());
+        let other = FaultInjector::new(noisy(43)).inject(frames());
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_ne!(bytes(&first), frames().concat());
+    }
+
+    #[test]
+    fn test_drop_and_duplicate() {
+        let mut faults = Faults {
+            drop: 1_f64,
+            ..Faults::default()
+        };
+        assert!(FaultInjector::new(faults.clone())
+            .inject(frames())
+            .is_empty());
+
+        faults.drop = 0_f64;
+        faults.duplicate = 1_f64;
+        let chunks = FaultInjector::new(faults).inject(vec![b"(5,42)".to_vec()]);
+        assert_eq!(bytes(&chunks), b"(5,42)(5,42)");
+    }
+
+    #[test]
+    fn test_reorder() {
+        let faults = Faults {
+            reorder: 1_f64,
+            ..Faults::default()
+        };
+        let mut injector = FaultInjector::new(faults);
+
+        let chunks = injector.inject(vec![b"(5,1)".to_vec(), b"(5,2)".to_vec()]);
+        assert_eq!(bytes(&chunks), b"(5,2)(5,1)");
+
+        assert!(injector.inject(vec![b"(5,3)".to_vec()]).is_empty());
+        let chunks = injector.inject(vec![b"(5,4)".to_vec()]);
+        assert_eq!(bytes(&chunks), b"(5,4)(5,3)");
+    }
+}
@@ -0,0 +1,345 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+pub mod bus;
+pub mod faults;
+
+use crate::config::Faults;
+use crate::protocol::codec::LumenCacheDeviceCodec;
+use crate::simulator::bus::Bus;
+use crate::simulator::faults::FaultInjector;
+use anyhow::{anyhow, Error, Result};
+use bytes::BytesMut;
+use futures::prelude::*;
+use std::sync::Arc;
+use tokio::io::{duplex, split, AsyncRead, AsyncWrite, AsyncWriteExt, DuplexStream};
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+use tokio_serial::SerialStream;
+use tokio_util::codec::{Encoder, FramedRead};
+
+const DUPLEX_BUFFER_SIZE: usize = 4096;
+
+/// Emulates a lumencache bus by answering commands like the modules on `bus` would
+#[derive(Clone)]
+pub struct Simulator {
+    bus: Arc<Mutex<Bus>>,
+    faults: Arc<Mutex<FaultInjector>>,
+}
+
+impl Simulator {
+    pub fn new(bus: Bus) -> Self {
+        Simulator::with_faults(bus, Faults::default())
+    }
+
+    /// Creates a simulator whose replies are disturbed by `faults`
+    pub fn with_faults(bus: Bus, faults: Faults) -> Self {
+        Simulator {
+            bus: Arc::new(Mutex::new(bus)),
+            faults: Arc::new(Mutex::new(FaultInjector::new(faults))),
+        }
+    }
+
+    /// Answers the commands received on `stream` until it ends
+    pub async fn serve<S>(&self, stream: S) -> Result<(), Error>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (reader, mut writer) = split(stream);
+        let mut commands = FramedRead::new(reader, LumenCacheDeviceCodec);
+
+        while let Some(command) = commands.next().await {
+            let command = command?;
+            log::trace!("Simulating {:?}", command);
+
+            let responses = self.bus.lock().await.handle(command);
+            let mut frames = Vec::new();
+
+            for response in responses {
+                let mut frame = BytesMut::new();
+                LumenCacheDeviceCodec.encode(response, &mut frame)?;
+                frames.push(frame.to_vec());
+            }
+
+            let chunks = self.faults.lock().await.inject(frames);
+
+            for chunk in chunks {
+                if !chunk.delay.is_zero() {
+                    sleep(chunk.delay).await;
+                }
+
+                writer.write_all(&chunk.bytes).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the adapter side of an in-memory stream which is served in the background
+    pub fn duplex(&self) -> DuplexStream {
+        let (adapter, bus) = duplex(DUPLEX_BUFFER_SIZE);
+        self.spawn(bus);
+        adapter
+    }
+
+    /// Serves every connection accepted on `listener`
+    pub async fn serve_tcp(&self, listener: TcpListener) -> Result<(), Error> {
+        loop {
+            let (stream, address) = listener.accept().await?;
+            log::info!("Simulating bus for {}", address);
+            self.spawn(stream);
+        }
+    }
+
+    /// Creates a pseudo terminal which is served in the background and returns its path
+    pub fn pty(&self) -> Result<String, Error> {
+        let (master, slave) = SerialStream::pair().map_err(|err| anyhow!(err))?;
+        let path = tokio_serial::SerialPort::name(&slave)
+            .ok_or_else(|| anyhow!("Pseudo terminal has no name"))?;
+        let simulator = self.clone();
+
+        tokio::spawn(async move {
+            // Closing the last handle of the slave side would hang up the master side
+            let _slave = slave;
+
+            if let Err(err) = simulator.serve(master).await {
+                log::warn!("Simulated bus failed: {}", err);
+            }
+        });
+
+        Ok(path)
+    }
+
+    fn spawn<S>(&self, stream: S)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let simulator = self.clone();
+
+        tokio::spawn(async move {
+            if let Err(err) = simulator.serve(stream).await {
+                log::warn!("Simulated bus failed: {}", err);
+            }
+        });
+    }
+}
+
+/// Runs a standalone simulator, e.g. `simulate 3 tcp 5000` or `simulate 3 pty`
+pub async fn run(args: &[String]) -> Result<(), Error> {
+    let usage = || anyhow!("Usage: simulate <modules> (tcp <port> | pty)");
+
+    let modules: usize = args.first().ok_or_else(usage)?.parse()?;
+    let simulator = Simulator::new(Bus::unassigned(modules));
+
+    match args.get(1).map(String::as_str) {
+        Some("tcp") => {
+            let port: u16 = args.get(2).ok_or_else(usage)?.parse()?;
+            let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+            log::info!("Simulating {} modules on port {}", modules, port);
+            simulator.serve_tcp(listener).await
+        }
+        Some("pty") => {
+            let path = simulator.pty()?;
+            log::info!("Simulating {} modules on {}", modules, path);
+            future::pending().await
+        }
+        _ => Err(usage()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::Controller;
+    use crate::protocol::codec::LumenCacheCodec;
+    use crate::protocol::decoder::{Config, Response, Scene, Value};
+    use crate::protocol::encoder::{AssignIdCommand, Commands, GetScenesCommand, SetValueCommand};
+    use crate::request::RequestResponse;
+    use crate::simulator::bus::SimulatedModule;
+    use crate::transport::simulator::SimulatorConnector;
+    use crate::transport::{Link, TransportEvent};
+    use serde_json::json;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio_serial::SerialPortBuilderExt;
+    use tokio_util::codec::Framed;
+
+    #[tokio::test]
+    async fn test_duplex() {
+        let simulator = Simulator::new(Bus::unassigned(2));
+        let mut framed = Framed::new(simulator.duplex(), LumenCacheCodec);
+
+        framed.send(Commands::Hail).await.unwrap();
+        let serial_number = match framed.next().await {
+            Some(Ok(Response::Config(Config {
+                hardware_serial_number,
+                ..
+            }))) => hardware_serial_number,
+            response => panic!("Unexpected {:?}", response),
+        };
+
+        framed
+            .send(Commands::AssignId(AssignIdCommand {
+                id: 5,
+                serial_number,
+            }))
+            .await
+            .unwrap();
+        assert!(matches!(
+            framed.next().await,
+            Some(Ok(Response::Config(Config { id: 5, .. })))
+        ));
+
+        framed
+            .send(Commands::GetScenes(GetScenesCommand { id: 5 }))
+            .await
+            .unwrap();
+
+        for _ in 0..bus::SCENE_COUNT {
+            assert!(matches!(framed.next().await, Some(Ok(Response::Scene(_)))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tcp() {
+        let simulator = Simulator::new(Bus::new(vec![SimulatedModule::new(
+            String::from("A"),
+            Some(5),
+        )]));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move { simulator.serve_tcp(listener).await });
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        stream.write_all(b"[5,42]").await.unwrap();
+
+        let mut buf = [0; 6];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"(5,42)");
+    }
+
+    #[tokio::test]
+    async fn test_pty() {
+        let simulator = Simulator::new(Bus::new(vec![SimulatedModule::new(
+            String::from("A"),
+            Some(5),
+        )]));
+
+        let path = simulator.pty().unwrap();
+        let port = tokio_serial::new(path, 38400).open_native_async().unwrap();
+        let mut framed = Framed::new(port, LumenCacheCodec);
+
+        framed
+            .send(Commands::SetValue(SetValueCommand { id: 5, value: 42 }))
+            .await
+            .unwrap();
+        assert!(matches!(
+            framed.next().await,
+            Some(Ok(Response::Value(Value { id: 5, value: 42 })))
+        ));
+    }
+
+    async fn noisy_controller(faults: Faults) -> Controller {
+        let config: crate::Config = serde_json::from_value(json!({
+            "expertSettings": {
+                "txDelayMs": 0,
+                "responseTimeoutMs": 100
+            }
+        }))
+        .unwrap();
+        let bus = Bus::new(vec![SimulatedModule::new(String::from("A"), Some(5))]);
+        let connector = SimulatorConnector::new(Simulator::with_faults(bus, faults));
+        let link = Link::start(
+            String::from("noisy"),
+            String::from("Noisy"),
+            connector,
+            &config,
+        );
+
+        let controller = Controller::start(config, link.transport);
+        let receiver = controller.clone();
+        let mut events = link.events;
+        assert!(matches!(
+            events.recv().await,
+            Some(TransportEvent::Connected)
+        ));
+
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                if let TransportEvent::Response(response) = event {
+                    receiver.check_response(&response).await;
+                }
+            }
+        });
+
+        controller
+    }
+
+    /// Alternately sets a value and reads the scenes of a module on a noisy bus, returns the
+    /// number of scene listings that succeeded
+    async fn noisy_round(seed: u64) -> usize {
+        let mut controller = noisy_controller(Faults {
+            seed,
+            drop: 0.002,
+            delay: 0.002,
+            max_delay_ms: 20,
+            duplicate: 0.002,
+            corrupt: 0.002,
+            reorder: 0.002,
+            garbage: 0.002,
+        })
+        .await;
+        // The simulated module starts with empty scene slots
+        let expected_scenes: Vec<Scene> = (1..=bus::SCENE_COUNT)
+            .map(|scene| Scene {
+                id: 5,
+                scene,
+                level: -1,
+                duration: -1,
+            })
+            .collect();
+        let mut listed = 0;
+
+        for value in 0..20 {
+            // Every value is set eventually, even if all attempts of a request timed out
+            let mut requests = 0;
+            loop {
+                requests += 1;
+                match controller.set_value(5, value).await.await.unwrap() {
+                    RequestResponse::Response {
+                        value: response, ..
+                    } => {
+                        assert_eq!(response, Value { id: 5, value });
+                        break;
+                    }
+                    RequestResponse::Timeout => assert!(requests < 3, "{} was never set", value),
+                }
+            }
+
+            // A listing is either complete and correct or times out
+            if let RequestResponse::Response {
+                value: mut scenes, ..
+            } = controller.request_scenes(5).await.await.unwrap()
+            {
+                scenes.sort_by_key(|scene| scene.scene);
+                assert_eq!(scenes, expected_scenes);
+                listed += 1;
+            }
+        }
+
+        listed
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_controller_on_noisy_bus() {
+        for seed in 0..5 {
+            // Scene listings span 64 frames, so some of them are hit by a fault
+            let listed = noisy_round(seed).await;
+            assert!(listed > 0, "No scene listing succeeded with seed {}", seed);
+        }
+    }
+}
@@ -3,12 +3,42 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
-use std::time::Instant;
-use tokio::time::{sleep, Duration};
+use tokio::time::{sleep, Duration, Instant};
+
+/// Amount by which the gap shrinks after each undisturbed response
+const ADDITIVE_DECREASE: Duration = Duration::from_millis(10);
+
+/// Factor by which the gap grows after each timeout
+const MULTIPLICATIVE_INCREASE: u32 = 2;
+
+/// Weight of a new sample in the smoothed round-trip time and timeout rate
+const SMOOTHING: f64 = 0.125;
+
+/// The gap is only shrunk while fewer responses than this time out
+const MAX_TIMEOUT_RATE: f64 = 0.05;
+
+/// A response slower than this multiple of the smoothed round-trip time indicates a busy bus
+const MAX_RTT_INFLATION: f64 = 2.0;
+
+/// Diagnostics of a `Throttle`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ThrottleStats {
+    pub gap: Duration,
+    pub smoothed_rtt: Option<Duration>,
+    pub timeout_rate: f64,
+}
+
+struct Adaptive {
+    min: Duration,
+    max: Duration,
+}
 
 pub struct Throttle {
     time_to_wait: Duration,
     last_time: Option<Instant>,
+    adaptive: Option<Adaptive>,
+    smoothed_rtt: Option<Duration>,
+    timeout_rate: f64,
 }
 
 impl Throttle {
@@ -16,6 +46,20 @@ impl Throttle {
         Self {
             time_to_wait: duration,
             last_time: None,
+            adaptive: None,
+            smoothed_rtt: None,
+            timeout_rate: 0.0,
+        }
+    }
+
+    /// Adjusts the gap between `min` and `max` like AIMD congestion control.
+    ///
+    /// The gap shrinks additively while responses arrive in time and grows
+    /// multiplicatively after a timeout.
+    pub fn adaptive(initial: Duration, min: Duration, max: Duration) -> Self {
+        Self {
+            adaptive: Some(Adaptive { min, max }),
+            ..Throttle::new(initial.clamp(min, max))
         }
     }
 
@@ -37,4 +81,114 @@ impl Throttle {
             self.reset_start();
         }
     }
+
+    /// Records a response which arrived `rtt` after its command was sent
+    pub fn on_response(&mut self, rtt: Duration) {
+        self.reset_start();
+
+        let smoothed_rtt = match self.smoothed_rtt {
+            Some(smoothed_rtt) => smoothed_rtt.mul_f64(1.0 - SMOOTHING) + rtt.mul_f64(SMOOTHING),
+            None => rtt,
+        };
+        let congested = rtt.as_secs_f64() > smoothed_rtt.as_secs_f64() * MAX_RTT_INFLATION;
+        self.smoothed_rtt = Some(smoothed_rtt);
+        self.timeout_rate *= 1.0 - SMOOTHING;
+
+        if let Some(adaptive) = &self.adaptive {
+            if !congested && self.timeout_rate < MAX_TIMEOUT_RATE {
+                let gap = self.time_to_wait.saturating_sub(ADDITIVE_DECREASE);
+                self.time_to_wait = gap.max(adaptive.min);
+            }
+        }
+    }
+
+    /// Records a command which was not answered in time
+    pub fn on_timeout(&mut self) {
+        self.timeout_rate = self.timeout_rate * (1.0 - SMOOTHING) + SMOOTHING;
+
+        if let Some(adaptive) = &self.adaptive {
+            let gap = self.time_to_wait * MULTIPLICATIVE_INCREASE;
+            self.time_to_wait = gap.min(adaptive.max);
+        }
+    }
+
+    /// Whether an adaptive gap reached its minimum or maximum
+    pub fn at_limit(&self) -> bool {
+        match &self.adaptive {
+            Some(adaptive) => {
+                self.time_to_wait == adaptive.min || self.time_to_wait == adaptive.max
+            }
+            None => false,
+        }
+    }
+
+    pub fn stats(&self) -> ThrottleStats {
+        ThrottleStats {
+            gap: self.time_to_wait,
+            smoothed_rtt: self.smoothed_rtt,
+            timeout_rate: self.timeout_rate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn test_fixed() {
+        let mut throttle = Throttle::new(ms(200));
+        throttle.on_timeout();
+        throttle.on_response(ms(20));
+        assert_eq!(throttle.stats().gap, ms(200));
+        assert_eq!(throttle.stats().smoothed_rtt, Some(ms(20)));
+    }
+
+    #[test]
+    fn test_aimd() {
+        let mut throttle = Throttle::adaptive(ms(200), ms(50), ms(1000));
+
+        for _ in 0..5 {
+            throttle.on_response(ms(20));
+        }
+        assert_eq!(throttle.stats().gap, ms(150));
+        assert!(!throttle.at_limit());
+
+        throttle.on_timeout();
+        throttle.on_timeout();
+        assert_eq!(throttle.stats().gap, ms(600));
+        throttle.on_timeout();
+        assert_eq!(throttle.stats().gap, ms(1000));
+        assert!(throttle.at_limit());
+
+        // The gap only shrinks again after the timeout rate went down
+        throttle.on_response(ms(20));
+        assert_eq!(throttle.stats().gap, ms(1000));
+        for _ in 0..30 {
+            throttle.on_response(ms(20));
+        }
+        assert!(throttle.stats().timeout_rate < MAX_TIMEOUT_RATE);
+        assert!(throttle.stats().gap < ms(1000));
+
+        for _ in 0..200 {
+            throttle.on_response(ms(20));
+        }
+        assert_eq!(throttle.stats().gap, ms(50));
+        assert!(throttle.at_limit());
+    }
+
+    #[test]
+    fn test_slow_response() {
+        let mut throttle = Throttle::adaptive(ms(200), ms(50), ms(1000));
+        throttle.on_response(ms(20));
+        assert_eq!(throttle.stats().gap, ms(190));
+
+        // A response far slower than usual holds the gap
+        throttle.on_response(ms(200));
+        assert_eq!(throttle.stats().gap, ms(190));
+    }
 }
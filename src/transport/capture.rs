@@ -0,0 +1,265 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::transport::stream::Connector;
+use anyhow::{anyhow, Error, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+
+#[derive(Clone, Copy, Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Direction {
+    /// Bytes sent to the bus
+    Tx,
+    /// Bytes received from the bus
+    Rx,
+}
+
+/// A single line of a capture file
+#[derive(Clone, Deserialize, Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Record {
+    /// Milliseconds since the capture was started
+    pub time_ms: u64,
+    pub direction: Direction,
+    /// The raw bytes, each one stored as the char with the same code point
+    pub data: String,
+}
+
+impl Record {
+    pub fn new(time_ms: u64, direction: Direction, bytes: &[u8]) -> Self {
+        Record {
+            time_ms,
+            direction,
+            data: bytes.iter().map(|byte| *byte as char).collect(),
+        }
+    }
+
+    pub fn bytes(&self) -> Result<Vec<u8>, Error> {
+        self.data
+            .chars()
+            .map(|c| u8::try_from(c as u32).map_err(|_| anyhow!("Invalid byte {:?}", c)))
+            .collect()
+    }
+}
+
+/// Parses a capture file with one JSON record per line
+pub fn parse_records(capture: &str) -> Result<Vec<Record>, Error> {
+    capture
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).map_err(Error::from))
+        .collect()
+}
+
+/// Writes the traffic of a link to a new capture file
+#[derive(Clone)]
+pub struct Capture {
+    start: Instant,
+    records: mpsc::UnboundedSender<Record>,
+}
+
+impl Capture {
+    pub fn create(path: &Path) -> Result<Self, Error> {
+        let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        std::thread::spawn(move || write_records(file, rx));
+
+        Ok(Capture {
+            start: Instant::now(),
+            records: tx,
+        })
+    }
+
+    fn record(&self, direction: Direction, bytes: &[u8]) {
+        if bytes.is_empty() {
+            return;
+        }
+
+        let time_ms = self.start.elapsed().as_millis() as u64;
+        let _ = self.records.send(Record::new(time_ms, direction, bytes));
+    }
+}
+
+fn write_records(file: File, mut records: mpsc::UnboundedReceiver<Record>) {
+    let mut writer = BufWriter::new(file);
+
+    while let Some(record) = records.blocking_recv() {
+        let result = serde_json::to_string(&record)
+            .map_err(io::Error::from)
+            .and_then(|line| writeln!(writer, "{}", line))
+            .and_then(|_| writer.flush());
+
+        if let Err(err) = result {
+            log::warn!("Could not write capture: {}", err);
+        }
+    }
+}
+
+/// Records all bytes read from and written to the wrapped stream
+pub struct CaptureStream<S> {
+    inner: S,
+    capture: Capture,
+}
+
+impl<S> AsyncRead for CaptureStream<S>
+where
+    S: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut this.inner).poll_read(cx, buf);
+
+        if let Poll::Ready(Ok(())) = result {
+            this.capture.record(Direction::Rx, &buf.filled()[filled..]);
+        }
+
+        result
+    }
+}
+
+impl<S> AsyncWrite for CaptureStream<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let result = Pin::new(&mut this.inner).poll_write(cx, buf);
+
+        if let Poll::Ready(Ok(written)) = result {
+            this.capture.record(Direction::Tx, &buf[..written]);
+        }
+
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Captures the traffic of every stream opened by the wrapped connector
+pub struct CaptureConnector<C> {
+    inner: C,
+    capture: Capture,
+}
+
+impl<C> CaptureConnector<C> {
+    pub fn new(inner: C, capture: Capture) -> Self {
+        CaptureConnector { inner, capture }
+    }
+}
+
+#[async_trait]
+impl<C> Connector for CaptureConnector<C>
+where
+    C: Connector,
+    C::Stream: Unpin,
+{
+    type Stream = CaptureStream<C::Stream>;
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    async fn connect(&mut self) -> Result<Self::Stream, Error> {
+        let inner = self.inner.connect().await?;
+
+        Ok(CaptureStream {
+            inner,
+            capture: self.capture.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+    use tokio::time::{sleep, Duration};
+
+    #[test]
+    fn test_record_bytes() {
+        let bytes = b"(13,\xff\x00\n37)";
+        let record = Record::new(12, Direction::Rx, bytes);
+        let line = serde_json::to_string(&record).unwrap();
+
+        assert_eq!(
+            line,
+            r#"{"timeMs":12,"direction":"rx","data":"(13,ÿ\u0000\n37)"}"#
+        );
+        assert_eq!(parse_records(&line).unwrap(), vec![record.clone()]);
+        assert_eq!(record.bytes().unwrap(), bytes);
+    }
+
+    #[tokio::test]
+    async fn test_capture_stream() {
+        let path = std::env::temp_dir().join(format!("lumencache-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let (client, mut bus) = duplex(64);
+        let mut stream = CaptureStream {
+            inner: client,
+            capture: Capture::create(&path).unwrap(),
+        };
+
+        stream.write_all(b"[13,256]").await.unwrap();
+        let mut buf = [0; 8];
+        bus.read_exact(&mut buf).await.unwrap();
+
+        bus.write_all(b"(13,37)").await.unwrap();
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        drop(stream);
+
+        // The records are written by a background thread
+        let mut records = Vec::new();
+
+        for _ in 0..100 {
+            records = parse_records(&fs::read_to_string(&path).unwrap()).unwrap();
+
+            if records.len() == 2 {
+                break;
+            }
+
+            sleep(Duration::from_millis(10)).await;
+        }
+
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, Direction::Tx);
+        assert_eq!(records[0].bytes().unwrap(), b"[13,256]");
+        assert_eq!(records[1].direction, Direction::Rx);
+        assert_eq!(records[1].bytes().unwrap(), b"(13,37)");
+
+        // A capture never continues an older session
+        assert!(Capture::create(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
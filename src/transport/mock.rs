@@ -0,0 +1,132 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::protocol::decoder::Response;
+use crate::protocol::encoder::Commands;
+use crate::transport::Transport;
+use anyhow::{anyhow, Error, Result};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::{sleep, Duration, Instant};
+
+/// Scripted reaction of the `MockTransport` to a single sent command
+#[derive(Clone, Debug)]
+pub enum Reply {
+    /// Delivers the responses after the delay
+    Respond(Duration, Vec<Response>),
+    /// Fails to send the command, like a disconnected transport
+    Fail,
+}
+
+impl Reply {
+    pub fn now(responses: Vec<Response>) -> Self {
+        Reply::Respond(Duration::from_millis(0), responses)
+    }
+
+    pub fn after(millis: u64, responses: Vec<Response>) -> Self {
+        Reply::Respond(Duration::from_millis(millis), responses)
+    }
+
+    pub fn silence() -> Self {
+        Reply::Respond(Duration::from_millis(0), Vec::new())
+    }
+}
+
+/// Transport which records the sent commands and answers them from a script.
+///
+/// Commands sent after the script ran out are not answered.
+pub struct MockTransport {
+    script: VecDeque<Reply>,
+    sent: Arc<StdMutex<Vec<(Instant, Commands)>>>,
+    responses: mpsc::UnboundedSender<Response>,
+}
+
+/// Test side of a `MockTransport`
+pub struct MockHandle {
+    sent: Arc<StdMutex<Vec<(Instant, Commands)>>>,
+    responses: mpsc::UnboundedSender<Response>,
+}
+
+impl MockTransport {
+    /// Returns the transport, its handle and the receiver of the responses to deliver
+    pub fn start(
+        script: Vec<Reply>,
+    ) -> (
+        Arc<Mutex<MockTransport>>,
+        MockHandle,
+        mpsc::UnboundedReceiver<Response>,
+    ) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let sent = Arc::new(StdMutex::new(Vec::new()));
+
+        let transport = MockTransport {
+            script: script.into(),
+            sent: sent.clone(),
+            responses: tx.clone(),
+        };
+
+        let handle = MockHandle {
+            sent,
+            responses: tx,
+        };
+
+        (Arc::new(Mutex::new(transport)), handle, rx)
+    }
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    async fn send(&mut self, command: Commands) -> Result<(), Error> {
+        self.sent
+            .lock()
+            .unwrap()
+            .push((Instant::now(), command.clone()));
+
+        match self.script.pop_front() {
+            Some(Reply::Respond(delay, responses)) => {
+                let tx = self.responses.clone();
+
+                tokio::spawn(async move {
+                    sleep(delay).await;
+
+                    for response in responses {
+                        let _ = tx.send(response);
+                    }
+                });
+
+                Ok(())
+            }
+            Some(Reply::Fail) => Err(anyhow!("Failed to send {:?}", command)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl MockHandle {
+    pub fn sent(&self) -> Vec<Commands> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, command)| command.clone())
+            .collect()
+    }
+
+    pub fn sent_at(&self) -> Vec<Instant> {
+        self.sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(instant, _)| *instant)
+            .collect()
+    }
+
+    /// Delivers a response which was not triggered by a command
+    pub fn inject(&self, response: Response) {
+        self.responses.send(response).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+pub mod capture;
+#[cfg(test)]
+pub mod mock;
+pub mod pty;
+pub mod replay;
+pub mod rfc2217;
+pub mod serial;
+pub mod simulator;
+pub mod stream;
+pub mod tcp;
+pub mod unix;
+
+use crate::backoff::Backoff;
+use crate::config::{Config, TcpProtocol};
+use crate::protocol::codec::{LumenCacheCodec, LumenCacheMonitorCodec};
+use crate::protocol::decoder::Response;
+use crate::protocol::encoder::Commands;
+use crate::protocol::monitor::Frame;
+use crate::simulator::bus::Bus;
+use crate::simulator::Simulator;
+use crate::transport::capture::{Capture, CaptureConnector};
+use crate::transport::pty::PtyConnector;
+use crate::transport::replay::ReplayConnector;
+use crate::transport::rfc2217::Rfc2217Connector;
+use crate::transport::serial::SerialConnector;
+use crate::transport::simulator::SimulatorConnector;
+use crate::transport::stream::{Connector, StreamTransport};
+use crate::transport::tcp::TcpConnector;
+use crate::transport::unix::UnixConnector;
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::Duration;
+
+#[async_trait]
+pub trait Transport: Send {
+    async fn send(&mut self, command: Commands) -> Result<(), Error>;
+}
+
+#[derive(Debug)]
+pub enum TransportEvent {
+    Connected,
+    Disconnected,
+    Response(Response),
+    /// A command sent by another controller on a monitored bus
+    Command(Commands),
+}
+
+impl From<Response> for TransportEvent {
+    fn from(response: Response) -> Self {
+        TransportEvent::Response(response)
+    }
+}
+
+impl From<Frame> for TransportEvent {
+    fn from(frame: Frame) -> Self {
+        match frame {
+            Frame::Command(command) => TransportEvent::Command(command),
+            Frame::Response(response) => TransportEvent::Response(response),
+        }
+    }
+}
+
+/// A configured connection to a lumencache bus
+pub struct Link {
+    pub id: String,
+    pub title: String,
+    pub transport: Arc<Mutex<dyn Transport>>,
+    pub events: mpsc::Receiver<TransportEvent>,
+}
+
+impl Link {
+    pub fn start<C>(id: String, title: String, connector: C, config: &Config) -> Self
+    where
+        C: Connector,
+        C::Stream: Unpin,
+    {
+        let (tx, rx) = mpsc::channel(100);
+        let transport = match capture(&id, config) {
+            Some(capture) => start_transport(CaptureConnector::new(connector, capture), config, tx),
+            None => start_transport(connector, config, tx),
+        };
+
+        Link {
+            id,
+            title,
+            transport,
+            events: rx,
+        }
+    }
+}
+
+fn start_transport<C>(
+    connector: C,
+    config: &Config,
+    events: mpsc::Sender<TransportEvent>,
+) -> Arc<Mutex<dyn Transport>>
+where
+    C: Connector,
+{
+    if config.expert_settings.passive {
+        StreamTransport::start(connector, LumenCacheMonitorCodec, backoff(config), events)
+    } else {
+        StreamTransport::start(connector, LumenCacheCodec, backoff(config), events)
+    }
+}
+
+/// Starts a link for each configured adapter
+pub fn start_links(config: &Config) -> Vec<Link> {
+    let mut links = Vec::new();
+
+    for adapter in &config.serial_adapters {
+        links.push(Link::start(
+            adapter.id.clone(),
+            adapter.title.clone(),
+            SerialConnector::new(adapter.port.clone(), adapter.line_settings.clone()),
+            config,
+        ));
+    }
+
+    for adapter in &config.tcp_adapters {
+        let id = adapter.id.clone();
+        let title = adapter.title.clone();
+        let host = adapter.host.clone();
+
+        links.push(match adapter.protocol {
+            TcpProtocol::Raw => {
+                Link::start(id, title, TcpConnector::new(host, adapter.port), config)
+            }
+            TcpProtocol::Rfc2217 => Link::start(
+                id,
+                title,
+                Rfc2217Connector::new(host, adapter.port, adapter.line_settings.clone()),
+                config,
+            ),
+        });
+    }
+
+    for adapter in &config.unix_adapters {
+        links.push(Link::start(
+            adapter.id.clone(),
+            adapter.title.clone(),
+            UnixConnector::new(adapter.path.clone()),
+            config,
+        ));
+    }
+
+    for adapter in &config.pty_adapters {
+        links.push(Link::start(
+            adapter.id.clone(),
+            adapter.title.clone(),
+            PtyConnector::new(adapter.path.clone()),
+            config,
+        ));
+    }
+
+    for adapter in &config.replay_adapters {
+        links.push(Link::start(
+            adapter.id.clone(),
+            adapter.title.clone(),
+            ReplayConnector::new(adapter.path.clone()),
+            config,
+        ));
+    }
+
+    for adapter in &config.simulator_adapters {
+        let simulator =
+            Simulator::with_faults(Bus::unassigned(adapter.modules), adapter.faults.clone());
+
+        links.push(Link::start(
+            adapter.id.clone(),
+            adapter.title.clone(),
+            SimulatorConnector::new(simulator),
+            config,
+        ));
+    }
+
+    links
+}
+
+fn backoff(config: &Config) -> Backoff {
+    Backoff::new(
+        Duration::from_millis(config.expert_settings.reconnect_delay_ms),
+        Duration::from_millis(config.expert_settings.max_reconnect_delay_ms),
+    )
+}
+
+/// Creates a capture file for this session of the link `id` if captures are enabled.
+///
+/// The records of a file are timed relative to the start of the session, so every start of
+/// the adapter writes a new file named after the link and the start time.
+fn capture(id: &str, config: &Config) -> Option<Capture> {
+    let directory = config.expert_settings.capture_directory.as_ref()?;
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs())
+        .unwrap_or(0);
+    let path = Path::new(directory).join(format!("{}-{}.jsonl", id, started));
+
+    match Capture::create(&path) {
+        Ok(capture) => {
+            log::info!("Capturing traffic of {} to {}", id, path.display());
+            Some(capture)
+        }
+        Err(err) => {
+            log::warn!("Could not create capture {}: {}", path.display(), err);
+            None
+        }
+    }
+}
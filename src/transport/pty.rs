@@ -0,0 +1,61 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::config::LineSettings;
+use crate::transport::serial::serial_port_builder;
+use crate::transport::stream::Connector;
+use anyhow::{anyhow, Error, Result};
+use async_trait::async_trait;
+use tokio_serial::{SerialPortBuilderExt, SerialStream};
+
+/// Opens a pseudo terminal, e.g. one created by a bus simulator.
+/// Line settings don't apply to a PTY, so the defaults are used to put it into raw mode.
+pub struct PtyConnector {
+    path: String,
+}
+
+impl PtyConnector {
+    pub fn new(path: String) -> Self {
+        PtyConnector { path }
+    }
+}
+
+#[async_trait]
+impl Connector for PtyConnector {
+    type Stream = SerialStream;
+
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+
+    async fn connect(&mut self) -> Result<Self::Stream, Error> {
+        serial_port_builder(&self.path, &LineSettings::default())
+            .open_native_async()
+            .map_err(|err| anyhow!(err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_pty() {
+        let (mut master, slave) = SerialStream::pair().unwrap();
+        let path = tokio_serial::SerialPort::name(&slave).unwrap();
+        let mut connector = PtyConnector::new(path);
+
+        let mut stream = connector.connect().await.unwrap();
+        master.write_all(b"(13,37)").await.unwrap();
+
+        // Raw mode passes the frame through unchanged
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"(13,37)");
+
+        drop(slave);
+    }
+}
@@ -0,0 +1,213 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::protocol::codec::LumenCacheDeviceCodec;
+use crate::transport::capture::{parse_records, Direction, Record};
+use crate::transport::stream::Connector;
+use anyhow::{anyhow, Error, Result};
+use async_trait::async_trait;
+use bytes::BytesMut;
+use futures::StreamExt;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use tokio::io::{duplex, split, AsyncWriteExt, DuplexStream};
+use tokio::time::{sleep, Duration};
+use tokio_util::codec::{Decoder, FramedRead};
+
+const DUPLEX_BUFFER_SIZE: usize = 4096;
+
+/// Plays a capture file back as if it was received from a bus
+pub struct ReplayConnector {
+    path: String,
+    failure: Arc<Mutex<Option<String>>>,
+}
+
+impl ReplayConnector {
+    pub fn new(path: String) -> Self {
+        ReplayConnector {
+            path,
+            failure: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for ReplayConnector {
+    type Stream = DuplexStream;
+
+    fn name(&self) -> String {
+        format!("replay of {}", self.path)
+    }
+
+    async fn connect(&mut self) -> Result<Self::Stream, Error> {
+        // Playing a diverged replay again would only diverge again
+        if let Some(failure) = self.failure.lock().unwrap().as_ref() {
+            return Err(anyhow!("Replay failed: {}", failure));
+        }
+
+        let records = parse_records(&fs::read_to_string(&self.path)?)?;
+        Ok(replay(records, self.failure.clone()))
+    }
+}
+
+/// Returns a stream which plays `records` back.
+///
+/// Received chunks are delivered with their captured delay to the preceding record. Before a
+/// chunk is delivered, the commands captured before it have to be sent again, so that the
+/// replay stays in step with the adapter. The replay stops at the first command which differs
+/// from the captured one and stores the reason in `failure`.
+pub fn replay(records: Vec<Record>, failure: Arc<Mutex<Option<String>>>) -> DuplexStream {
+    let (adapter, bus) = duplex(DUPLEX_BUFFER_SIZE);
+
+    tokio::spawn(async move {
+        if let Err(err) = play(records, bus).await {
+            log::error!("Stopping replay: {}", err);
+            *failure.lock().unwrap() = Some(err.to_string());
+        }
+    });
+
+    adapter
+}
+
+async fn play(records: Vec<Record>, bus: DuplexStream) -> Result<(), Error> {
+    let (reader, mut writer) = split(bus);
+    let mut sent = FramedRead::new(reader, LumenCacheDeviceCodec);
+    let mut captured = BytesMut::new();
+    let mut last_time_ms = records.first().map(|record| record.time_ms).unwrap_or(0);
+
+    for record in records {
+        let bytes = match record.bytes() {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                log::warn!("Skipping record at {} ms: {}", record.time_ms, err);
+                continue;
+            }
+        };
+
+        match record.direction {
+            Direction::Tx => {
+                captured.extend_from_slice(&bytes);
+
+                while let Some(expected) = LumenCacheDeviceCodec.decode(&mut captured)? {
+                    let command = match sent.next().await {
+                        Some(command) => command?,
+                        None => {
+                            log::debug!("Replay ended before all commands were sent");
+                            return Ok(());
+                        }
+                    };
+
+                    if command != expected {
+                        return Err(anyhow!(
+                            "Sent {:?} instead of the captured {:?} at {} ms",
+                            command,
+                            expected,
+                            record.time_ms
+                        ));
+                    }
+                }
+            }
+            Direction::Rx => {
+                let delay = record.time_ms.saturating_sub(last_time_ms);
+                sleep(Duration::from_millis(delay)).await;
+
+                if writer.write_all(&bytes).await.is_err() {
+                    log::debug!("Replay ended before all responses were received");
+                    return Ok(());
+                }
+            }
+        }
+
+        last_time_ms = record.time_ms;
+    }
+
+    log::info!("Replay finished");
+
+    // Keep the stream open, so that the adapter does not reconnect and replay it again
+    while sent.next().await.is_some() {}
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backoff::Backoff;
+    use crate::protocol::codec::LumenCacheCodec;
+    use crate::protocol::decoder::{Response, Value};
+    use crate::protocol::encoder::{Commands, GetValueCommand};
+    use crate::transport::stream::StreamTransport;
+    use crate::transport::{Transport, TransportEvent};
+    use tokio::io::AsyncReadExt;
+    use tokio::sync::mpsc;
+    use tokio::time::Instant;
+
+    // Captured from a gateway which split a reply and prefixed it with line noise
+    const CAPTURE: &str = r#"
+{"timeMs":0,"direction":"tx","data":"[13,256]"}
+{"timeMs":40,"direction":"rx","data":"\u0000ÿ(13,"}
+{"timeMs":45,"direction":"rx","data":"37)"}
+{"timeMs":300,"direction":"tx","data":"[13,256]"}
+{"timeMs":320,"direction":"rx","data":"(13,38)"}
+"#;
+
+    async fn next_response(events: &mut mpsc::Receiver<TransportEvent>) -> Response {
+        match events.recv().await {
+            Some(TransportEvent::Response(response)) => response,
+            event => panic!("Unexpected {:?}", event),
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replay() {
+        let path = std::env::temp_dir().join(format!("lumencache-replay-{}", std::process::id()));
+        fs::write(&path, CAPTURE).unwrap();
+
+        let (tx, mut events) = mpsc::channel(10);
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(100));
+        let connector = ReplayConnector::new(path.to_string_lossy().into_owned());
+        let transport = StreamTransport::start(connector, LumenCacheCodec, backoff, tx);
+        assert!(matches!(
+            events.recv().await,
+            Some(TransportEvent::Connected)
+        ));
+
+        for value in [37, 38] {
+            let start = Instant::now();
+            transport
+                .lock()
+                .await
+                .send(Commands::GetValue(GetValueCommand { id: 13 }))
+                .await
+                .unwrap();
+
+            assert_eq!(
+                next_response(&mut events).await,
+                Response::Value(Value { id: 13, value })
+            );
+            assert!(start.elapsed() >= Duration::from_millis(20));
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_diverged_replay() {
+        let path = std::env::temp_dir().join(format!("lumencache-diverged-{}", std::process::id()));
+        fs::write(&path, CAPTURE).unwrap();
+
+        let mut connector = ReplayConnector::new(path.to_string_lossy().into_owned());
+        let mut stream = connector.connect().await.unwrap();
+        stream.write_all(b"[14,256]").await.unwrap();
+
+        // The replay closes the stream instead of answering a different command
+        let mut buf = Vec::new();
+        stream.read_to_end(&mut buf).await.unwrap();
+        assert!(buf.is_empty());
+        assert!(connector.connect().await.is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,626 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::config::{FlowControl, LineSettings, Parity};
+use crate::transport::stream::Connector;
+use anyhow::{anyhow, Error, Result};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Duration};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const COM_PORT_OPTION: u8 = 44;
+const SET_BAUDRATE: u8 = 1;
+const SET_DATASIZE: u8 = 2;
+const SET_PARITY: u8 = 3;
+const SET_STOPSIZE: u8 = 4;
+const SET_CONTROL: u8 = 5;
+
+/// Added to a com port command by the server when it acknowledges the command
+const SERVER_OFFSET: u8 = 100;
+
+const NEGOTIATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+const READ_BUFFER_SIZE: usize = 1024;
+
+/// Connects to a TCP gateway speaking RFC 2217 and configures its serial line
+pub struct Rfc2217Connector {
+    host: String,
+    port: u16,
+    line_settings: LineSettings,
+}
+
+impl Rfc2217Connector {
+    pub fn new(host: String, port: u16, line_settings: LineSettings) -> Self {
+        Rfc2217Connector {
+            host,
+            port,
+            line_settings,
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for Rfc2217Connector {
+    type Stream = TelnetStream<TcpStream>;
+
+    fn name(&self) -> String {
+        format!("rfc2217://{}:{}", self.host, self.port)
+    }
+
+    async fn connect(&mut self) -> Result<Self::Stream, Error> {
+        let stream = TcpStream::connect((self.host.as_str(), self.port)).await?;
+        let mut stream = TelnetStream::new(stream);
+
+        timeout(NEGOTIATION_TIMEOUT, stream.negotiate(&self.line_settings))
+            .await
+            .map_err(|_| anyhow!("Server did not complete the com port negotiation"))??;
+
+        Ok(stream)
+    }
+}
+
+/// Com port commands and their values which set up the serial line
+fn line_requests(line_settings: &LineSettings) -> Vec<(u8, Vec<u8>)> {
+    let parity = match line_settings.parity {
+        Parity::None => 1,
+        Parity::Odd => 2,
+        Parity::Even => 3,
+    };
+
+    let flow_control = match line_settings.flow_control {
+        FlowControl::None => 1,
+        FlowControl::Software => 2,
+        FlowControl::Hardware => 3,
+    };
+
+    vec![
+        (SET_BAUDRATE, line_settings.baud_rate.to_be_bytes().to_vec()),
+        (SET_DATASIZE, vec![line_settings.data_bits]),
+        (SET_PARITY, vec![parity]),
+        (SET_STOPSIZE, vec![line_settings.stop_bits]),
+        (SET_CONTROL, vec![flow_control]),
+    ]
+}
+
+fn subnegotiation(bytes: &mut Vec<u8>, command: u8, value: &[u8]) {
+    bytes.extend_from_slice(&[IAC, SB, COM_PORT_OPTION, command]);
+    escape(bytes, value);
+    bytes.extend_from_slice(&[IAC, SE]);
+}
+
+fn escape(bytes: &mut Vec<u8>, data: &[u8]) {
+    for byte in data {
+        if *byte == IAC {
+            bytes.push(IAC);
+        }
+
+        bytes.push(*byte);
+    }
+}
+
+/// A telnet command received from the server
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TelnetCommand {
+    Do(u8),
+    Dont(u8),
+    Will(u8),
+    Wont(u8),
+    /// The unescaped bytes between `IAC SB` and `IAC SE`
+    Subnegotiation(Vec<u8>),
+}
+
+impl TelnetCommand {
+    /// Returns the answer refusing any option other than the com port option
+    fn refusal(&self) -> Option<[u8; 3]> {
+        match self {
+            TelnetCommand::Do(option) if *option != COM_PORT_OPTION => Some([IAC, WONT, *option]),
+            TelnetCommand::Will(option) => Some([IAC, DONT, *option]),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TelnetState {
+    Data,
+    Iac,
+    Option(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// Splits a byte stream into data and telnet commands
+#[derive(Debug)]
+struct TelnetFilter {
+    state: TelnetState,
+    subnegotiation: Vec<u8>,
+}
+
+impl TelnetFilter {
+    fn new() -> Self {
+        TelnetFilter {
+            state: TelnetState::Data,
+            subnegotiation: Vec::new(),
+        }
+    }
+
+    /// Appends the data bytes of `input` to `output` and its commands to `commands`
+    fn filter(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        commands: &mut VecDeque<TelnetCommand>,
+    ) {
+        for byte in input {
+            self.state = match (self.state, *byte) {
+                (TelnetState::Data, IAC) => TelnetState::Iac,
+                (TelnetState::Data, byte) => {
+                    output.push(byte);
+                    TelnetState::Data
+                }
+                (TelnetState::Iac, IAC) => {
+                    output.push(IAC);
+                    TelnetState::Data
+                }
+                (TelnetState::Iac, verb @ WILL..=DONT) => TelnetState::Option(verb),
+                (TelnetState::Iac, SB) => {
+                    self.subnegotiation.clear();
+                    TelnetState::Subnegotiation
+                }
+                (TelnetState::Iac, _) => TelnetState::Data,
+                (TelnetState::Option(verb), option) => {
+                    commands.push_back(match verb {
+                        DO => TelnetCommand::Do(option),
+                        DONT => TelnetCommand::Dont(option),
+                        WILL => TelnetCommand::Will(option),
+                        _ => TelnetCommand::Wont(option),
+                    });
+                    TelnetState::Data
+                }
+                (TelnetState::Subnegotiation, IAC) => TelnetState::SubnegotiationIac,
+                (TelnetState::Subnegotiation, byte) => {
+                    self.subnegotiation.push(byte);
+                    TelnetState::Subnegotiation
+                }
+                (TelnetState::SubnegotiationIac, SE) => {
+                    commands.push_back(TelnetCommand::Subnegotiation(
+                        self.subnegotiation.split_off(0),
+                    ));
+                    TelnetState::Data
+                }
+                (TelnetState::SubnegotiationIac, byte) => {
+                    self.subnegotiation.push(byte);
+                    TelnetState::Subnegotiation
+                }
+            };
+        }
+    }
+}
+
+/// Wraps a stream with telnet framing, stripping commands from the received bytes and
+/// escaping IAC bytes in the sent ones.
+///
+/// Options requested by the server other than the com port option are refused.
+pub struct TelnetStream<S> {
+    inner: S,
+    filter: TelnetFilter,
+    raw: Vec<u8>,
+    received: Vec<u8>,
+    commands: VecDeque<TelnetCommand>,
+    pending: Vec<u8>,
+}
+
+impl<S> TelnetStream<S> {
+    pub fn new(inner: S) -> Self {
+        TelnetStream {
+            inner,
+            filter: TelnetFilter::new(),
+            raw: vec![0; READ_BUFFER_SIZE],
+            received: Vec::new(),
+            commands: VecDeque::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Queues the refusals of the received commands for sending
+    fn answer_commands(&mut self) {
+        while let Some(command) = self.commands.pop_front() {
+            self.answer(&command);
+        }
+    }
+
+    fn answer(&mut self, command: &TelnetCommand) {
+        log::trace!("Received telnet command {:?}", command);
+
+        if let Some(refusal) = command.refusal() {
+            self.pending.extend_from_slice(&refusal);
+        }
+    }
+
+    /// Moves up to `buf.remaining()` received data bytes into `buf`
+    fn take_received(&mut self, buf: &mut ReadBuf<'_>) {
+        let len = self.received.len().min(buf.remaining());
+        buf.put_slice(&self.received[..len]);
+        self.received.drain(..len);
+    }
+}
+
+impl<S> TelnetStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    /// Enables the com port option and sets the line parameters, failing if the server
+    /// refuses the option.
+    ///
+    /// A server acknowledges the value it actually uses, which may differ from the requested
+    /// one, e.g. for a baud rate its UART can't generate, so that is only logged.
+    async fn negotiate(&mut self, line_settings: &LineSettings) -> Result<(), Error> {
+        self.inner.write_all(&[IAC, WILL, COM_PORT_OPTION]).await?;
+
+        loop {
+            match self.next_command().await? {
+                TelnetCommand::Do(COM_PORT_OPTION) => break,
+                TelnetCommand::Dont(COM_PORT_OPTION) => {
+                    return Err(anyhow!("Server refused the com port option"));
+                }
+                _ => {}
+            }
+        }
+
+        let mut requests = line_requests(line_settings);
+        let mut bytes = Vec::new();
+
+        for (command, value) in &requests {
+            subnegotiation(&mut bytes, *command, value);
+        }
+
+        self.inner.write_all(&bytes).await?;
+
+        while !requests.is_empty() {
+            let data = match self.next_command().await? {
+                TelnetCommand::Subnegotiation(data) => data,
+                _ => continue,
+            };
+
+            if let [COM_PORT_OPTION, acknowledged, value @ ..] = data.as_slice() {
+                let index = requests
+                    .iter()
+                    .position(|(command, _)| command + SERVER_OFFSET == *acknowledged);
+
+                if let Some(index) = index {
+                    let (command, expected) = requests.remove(index);
+
+                    if value != expected.as_slice() {
+                        log::warn!(
+                            "Server set com port parameter {} to {:?} instead of {:?}",
+                            command,
+                            value,
+                            expected
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Waits for the next telnet command and answers it, keeping data received meanwhile
+    async fn next_command(&mut self) -> Result<TelnetCommand, Error> {
+        loop {
+            if let Some(command) = self.commands.pop_front() {
+                self.answer(&command);
+                self.inner.write_all(&self.pending).await?;
+                self.pending.clear();
+
+                return Ok(command);
+            }
+
+            let read = self.inner.read(&mut self.raw).await?;
+
+            if read == 0 {
+                return Err(anyhow!("Server closed the connection during negotiation"));
+            }
+
+            self.filter
+                .filter(&self.raw[..read], &mut self.received, &mut self.commands);
+        }
+    }
+}
+
+impl<S> TelnetStream<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.pending.is_empty() {
+            match Pin::new(&mut self.inner).poll_write(cx, &self.pending)? {
+                Poll::Ready(0) => return Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+                Poll::Ready(n) => {
+                    self.pending.drain(..n);
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S> AsyncRead for TelnetStream<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if !this.received.is_empty() {
+            this.take_received(buf);
+            return Poll::Ready(Ok(()));
+        }
+
+        loop {
+            let len = this.raw.len().min(buf.remaining());
+            let mut raw_buf = ReadBuf::new(&mut this.raw[..len]);
+
+            let read = match Pin::new(&mut this.inner).poll_read(cx, &mut raw_buf)? {
+                Poll::Ready(()) => raw_buf.filled().len(),
+                Poll::Pending => return Poll::Pending,
+            };
+
+            if read == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            this.filter
+                .filter(&this.raw[..read], &mut this.received, &mut this.commands);
+            this.answer_commands();
+            // Refusals are sent along with the next command if the socket is busy
+            let _ = this.poll_write_pending(cx)?;
+
+            // A chunk of only telnet commands must not be mistaken for the end of stream
+            if !this.received.is_empty() {
+                this.take_received(buf);
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for TelnetStream<S>
+where
+    S: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+
+        if this.poll_write_pending(cx)?.is_pending() {
+            return Poll::Pending;
+        }
+
+        escape(&mut this.pending, buf);
+        let _ = this.poll_write_pending(cx)?;
+
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.poll_write_pending(cx)?.is_pending() {
+            return Poll::Pending;
+        }
+
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        if this.poll_write_pending(cx)?.is_pending() {
+            return Poll::Pending;
+        }
+
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn filter(chunks: &[&[u8]]) -> (Vec<u8>, Vec<TelnetCommand>) {
+        let mut filter = TelnetFilter::new();
+        let mut output = Vec::new();
+        let mut commands = VecDeque::new();
+
+        for chunk in chunks {
+            filter.filter(chunk, &mut output, &mut commands);
+        }
+
+        (output, commands.into())
+    }
+
+    fn data(chunks: &[&[u8]]) -> Vec<u8> {
+        filter(chunks).0
+    }
+
+    #[test]
+    fn test_filter() {
+        assert_eq!(data(&[b"(13,37)"]), b"(13,37)");
+        assert_eq!(data(&[b"(13,\xff\xfb\x2c37)"]), b"(13,37)");
+        assert_eq!(
+            data(&[b"{1,\xff\xfa\x2c\x65\x00\x00\x96\x00\xff\xf02}"]),
+            b"{1,2}"
+        );
+        assert_eq!(data(&[b"\xff\xff"]), b"\xff");
+        assert_eq!(data(&[b"\xff\xf1(1,2)"]), b"(1,2)");
+        assert_eq!(data(&[b"\xff\xfa\x2c\xff\xff\xff\xf0(1,2)"]), b"(1,2)");
+        assert_eq!(data(&[b"(1", b"\xff", b"\xfd", b"\x2c", b",2)"]), b"(1,2)");
+    }
+
+    #[test]
+    fn test_commands() {
+        let (_, commands) = filter(&[
+            b"\xff\xfd\x2c\xff\xfb\x01\xff\xfe\x03\xff",
+            b"\xfc\x18\xff\xfa\x2c\x65\x00\xff\xff\xff\xf0",
+        ]);
+
+        assert_eq!(
+            commands,
+            vec![
+                TelnetCommand::Do(COM_PORT_OPTION),
+                TelnetCommand::Will(1),
+                TelnetCommand::Dont(3),
+                TelnetCommand::Wont(24),
+                TelnetCommand::Subnegotiation(vec![COM_PORT_OPTION, 101, 0, IAC]),
+            ]
+        );
+        assert_eq!(commands[0].refusal(), None);
+        assert_eq!(commands[1].refusal(), Some([IAC, DONT, 1]));
+        assert_eq!(commands[2].refusal(), None);
+    }
+
+    #[test]
+    fn test_line_requests() {
+        let line_settings = LineSettings {
+            baud_rate: 9600,
+            data_bits: 7,
+            parity: Parity::Even,
+            stop_bits: 2,
+            flow_control: FlowControl::Hardware,
+        };
+
+        assert_eq!(
+            line_requests(&line_settings),
+            vec![
+                (SET_BAUDRATE, vec![0, 0, 0x25, 0x80]),
+                (SET_DATASIZE, vec![7]),
+                (SET_PARITY, vec![3]),
+                (SET_STOPSIZE, vec![2]),
+                (SET_CONTROL, vec![3]),
+            ]
+        );
+
+        let mut bytes = Vec::new();
+        subnegotiation(&mut bytes, SET_BAUDRATE, &[0, 0, 0x25, 0xff]);
+        assert_eq!(bytes, [IAC, SB, 44, 1, 0, 0, 0x25, IAC, IAC, IAC, SE]);
+    }
+
+    /// Accepts a connection and answers the handshake with `offer`, followed by
+    /// acknowledgements of the line settings with the baud rate replaced by `baud_rate`
+    async fn accept(listener: TcpListener, offer: &[u8], baud_rate: u32) -> TcpStream {
+        let (mut socket, _) = listener.accept().await.unwrap();
+
+        let mut buf = [0; 3];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [IAC, WILL, COM_PORT_OPTION]);
+        socket.write_all(offer).await.unwrap();
+
+        if offer.ends_with(&[IAC, DONT, COM_PORT_OPTION]) {
+            return socket;
+        }
+
+        // The refusals of the other options arrive before the line settings
+        let mut buf = [0; 6];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [IAC, DONT, 1, IAC, WONT, 24]);
+
+        let mut expected = Vec::new();
+        let mut acknowledgements = b"(13,".to_vec();
+
+        for (command, value) in line_requests(&LineSettings::default()) {
+            subnegotiation(&mut expected, command, &value);
+
+            let value = match command {
+                SET_BAUDRATE => baud_rate.to_be_bytes().to_vec(),
+                _ => value,
+            };
+            subnegotiation(&mut acknowledgements, command + SERVER_OFFSET, &value);
+        }
+
+        let mut buf = vec![0; expected.len()];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, expected);
+        socket.write_all(&acknowledgements).await.unwrap();
+
+        socket
+    }
+
+    async fn connect(
+        offer: &'static [u8],
+        baud_rate: u32,
+    ) -> (Result<TelnetStream<TcpStream>>, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(accept(listener, offer, baud_rate));
+
+        let mut connector =
+            Rfc2217Connector::new(String::from("127.0.0.1"), port, LineSettings::default());
+        let stream = connector.connect().await;
+
+        (stream, server.await.unwrap())
+    }
+
+    const OFFER: &[u8] = b"\xff\xfb\x01\xff\xfd\x18\xff\xfd\x2c";
+
+    #[tokio::test]
+    async fn test_fake_server() {
+        let (stream, mut socket) = connect(OFFER, 38400).await;
+        let mut stream = stream.unwrap();
+
+        // A later option request is refused as well
+        socket.write_all(b"\xff\xfb\x03").await.unwrap();
+        socket.write_all(b"37)").await.unwrap();
+
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"(13,37)");
+
+        stream.write_all(b"[13,256]").await.unwrap();
+        let mut buf = [0; 11];
+        socket.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"\xff\xfe\x03[13,256]");
+    }
+
+    #[tokio::test]
+    async fn test_refused_option() {
+        let (stream, _socket) = connect(b"\xff\xfe\x2c", 38400).await;
+        assert!(stream.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_different_baud_rate() {
+        // The server uses another baud rate, which is logged but doesn't fail the connection
+        let (stream, _socket) = connect(OFFER, 9600).await;
+        let mut stream = stream.unwrap();
+
+        let mut buf = [0; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"(13,");
+    }
+}
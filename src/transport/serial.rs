@@ -0,0 +1,242 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::config::{FlowControl, LineSettings, Parity};
+use crate::transport::stream::Connector;
+use anyhow::{anyhow, Error, Result};
+use async_trait::async_trait;
+use std::fs;
+use std::path::Path;
+use tokio_serial::{DataBits, SerialPortBuilder, SerialPortBuilderExt, SerialStream, StopBits};
+
+const SERIAL_BY_ID: &str = "/dev/serial/by-id";
+
+/// Opens a serial port, e.g. a USB adapter which may be unplugged at any time
+pub struct SerialConnector {
+    path: String,
+    line_settings: LineSettings,
+}
+
+impl SerialConnector {
+    pub fn new(path: String, line_settings: LineSettings) -> Self {
+        SerialConnector {
+            path,
+            line_settings,
+        }
+    }
+}
+
+#[async_trait]
+impl Connector for SerialConnector {
+    type Stream = SerialStream;
+
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+
+    async fn connect(&mut self) -> Result<Self::Stream, Error> {
+        let stream = serial_port_builder(&self.path, &self.line_settings)
+            .open_native_async()
+            .map_err(|err| anyhow!(err))?;
+
+        if let Some(stable_path) = stable_path(&self.path, Path::new(SERIAL_BY_ID)) {
+            log::debug!("Using {} to reopen {}", stable_path, self.path);
+            self.path = stable_path;
+        }
+
+        Ok(stream)
+    }
+}
+
+pub(crate) fn serial_port_builder(path: &str, line_settings: &LineSettings) -> SerialPortBuilder {
+    let data_bits = match line_settings.data_bits {
+        5 => DataBits::Five,
+        6 => DataBits::Six,
+        7 => DataBits::Seven,
+        _ => DataBits::Eight,
+    };
+
+    let stop_bits = match line_settings.stop_bits {
+        2 => StopBits::Two,
+        _ => StopBits::One,
+    };
+
+    let parity = match line_settings.parity {
+        Parity::None => tokio_serial::Parity::None,
+        Parity::Odd => tokio_serial::Parity::Odd,
+        Parity::Even => tokio_serial::Parity::Even,
+    };
+
+    let flow_control = match line_settings.flow_control {
+        FlowControl::None => tokio_serial::FlowControl::None,
+        FlowControl::Software => tokio_serial::FlowControl::Software,
+        FlowControl::Hardware => tokio_serial::FlowControl::Hardware,
+    };
+
+    tokio_serial::new(path, line_settings.baud_rate)
+        .data_bits(data_bits)
+        .stop_bits(stop_bits)
+        .parity(parity)
+        .flow_control(flow_control)
+}
+
+/// Looks up the link of `path` in `by_id`, e.g. `/dev/serial/by-id`, which survives a
+/// re-enumeration of the USB device
+fn stable_path(path: &str, by_id: &Path) -> Option<String> {
+    if Path::new(path).starts_with(by_id) {
+        return None;
+    }
+
+    let device = fs::canonicalize(path).ok()?;
+
+    fs::read_dir(by_id)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|link| fs::canonicalize(link).ok().as_ref() == Some(&device))
+        .map(|link| link.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backoff::Backoff;
+    use crate::protocol::codec::LumenCacheCodec;
+    use crate::protocol::decoder::{Response, Value};
+    use crate::transport::stream::StreamTransport;
+    use crate::transport::TransportEvent;
+    use std::path::PathBuf;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::mpsc;
+    use tokio::time::{timeout, Duration};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lumencache-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    async fn next_event(rx: &mut mpsc::Receiver<TransportEvent>) -> Option<TransportEvent> {
+        timeout(Duration::from_secs(5), rx.recv()).await.unwrap()
+    }
+
+    fn pty_pair(link: &Path) -> SerialStream {
+        let (master, slave) = SerialStream::pair().unwrap();
+        let name = tokio_serial::SerialPort::name(&slave).unwrap();
+        drop(slave);
+
+        let _ = fs::remove_file(link);
+        std::os::unix::fs::symlink(name, link).unwrap();
+
+        master
+    }
+
+    #[tokio::test]
+    async fn test_serial_reopen() {
+        let dir = temp_dir("reopen");
+        let link = dir.join("ttyLumenCache");
+        let mut connector =
+            SerialConnector::new(link.to_string_lossy().into_owned(), LineSettings::default());
+
+        // The adapter is unplugged
+        assert!(connector.connect().await.is_err());
+
+        let mut master = pty_pair(&link);
+        let mut stream = connector.connect().await.unwrap();
+        master.write_all(b"(13,37)").await.unwrap();
+
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"(13,37)");
+
+        // The configured path is kept when there is no stable link to the device
+        assert_eq!(connector.name(), link.to_string_lossy());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_serial_reconnect() {
+        let dir = temp_dir("reconnect");
+        let link = dir.join("ttyLumenCache");
+        let connector =
+            SerialConnector::new(link.to_string_lossy().into_owned(), LineSettings::default());
+        let (tx, mut rx) = mpsc::channel(10);
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(50));
+
+        let mut master = pty_pair(&link);
+        let _transport = StreamTransport::start(connector, LumenCacheCodec, backoff, tx);
+
+        assert!(matches!(
+            next_event(&mut rx).await,
+            Some(TransportEvent::Connected)
+        ));
+        master.write_all(b"(13,37)").await.unwrap();
+        assert!(matches!(
+            next_event(&mut rx).await,
+            Some(TransportEvent::Response(Response::Value(Value {
+                id: 13,
+                value: 37
+            })))
+        ));
+
+        // The device disappears while connected and comes back as a new PTY
+        drop(master);
+        assert!(matches!(
+            next_event(&mut rx).await,
+            Some(TransportEvent::Disconnected)
+        ));
+
+        let mut master = pty_pair(&link);
+        assert!(matches!(
+            next_event(&mut rx).await,
+            Some(TransportEvent::Connected)
+        ));
+        master.write_all(b"(13,38)").await.unwrap();
+        assert!(matches!(
+            next_event(&mut rx).await,
+            Some(TransportEvent::Response(Response::Value(Value {
+                id: 13,
+                value: 38
+            })))
+        ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_stable_path() {
+        let dir = temp_dir("by-id");
+        let by_id = dir.join("by-id");
+        fs::create_dir_all(&by_id).unwrap();
+
+        let device = dir.join("ttyUSB0");
+        let other = dir.join("ttyUSB1");
+        fs::write(&device, b"").unwrap();
+        fs::write(&other, b"").unwrap();
+
+        let link = by_id.join("usb-LumenCache_Bridge_1234-if00-port0");
+        std::os::unix::fs::symlink(&device, &link).unwrap();
+        std::os::unix::fs::symlink(&other, by_id.join("usb-Other_5678-if00-port0")).unwrap();
+
+        assert_eq!(
+            stable_path(&device.to_string_lossy(), &by_id),
+            Some(link.to_string_lossy().into_owned())
+        );
+        // A link is already stable
+        assert_eq!(stable_path(&link.to_string_lossy(), &by_id), None);
+        // Without a link the configured path is kept
+        assert_eq!(
+            stable_path(&dir.join("ttyUSB2").to_string_lossy(), &by_id),
+            None
+        );
+        assert_eq!(
+            stable_path(&device.to_string_lossy(), &dir.join("missing")),
+            None
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
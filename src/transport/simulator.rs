@@ -0,0 +1,34 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::simulator::Simulator;
+use crate::transport::stream::Connector;
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use tokio::io::DuplexStream;
+
+/// Connects to an in-process simulated bus
+pub struct SimulatorConnector {
+    simulator: Simulator,
+}
+
+impl SimulatorConnector {
+    pub fn new(simulator: Simulator) -> Self {
+        SimulatorConnector { simulator }
+    }
+}
+
+#[async_trait]
+impl Connector for SimulatorConnector {
+    type Stream = DuplexStream;
+
+    fn name(&self) -> String {
+        String::from("simulator")
+    }
+
+    async fn connect(&mut self) -> Result<Self::Stream, Error> {
+        Ok(self.simulator.duplex())
+    }
+}
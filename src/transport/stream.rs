@@ -0,0 +1,317 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::backoff::Backoff;
+use crate::protocol::codec::LumenCacheCodec;
+use crate::protocol::encoder::Commands;
+use crate::transport::{Transport, TransportEvent};
+use anyhow::{anyhow, Error, Result};
+use async_trait::async_trait;
+use futures::prelude::*;
+use futures::SinkExt;
+use std::sync::Arc;
+use tokio::io::{split, AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::select;
+use tokio::sync::{mpsc, Mutex};
+use tokio::time::sleep;
+use tokio_util::codec::{Decoder, FramedRead, FramedWrite};
+
+/// Opens the underlying stream of a `StreamTransport`
+#[async_trait]
+pub trait Connector: Send + 'static {
+    type Stream: AsyncRead + AsyncWrite + Send + 'static;
+
+    /// Name of the link used for logging
+    fn name(&self) -> String;
+
+    async fn connect(&mut self) -> Result<Self::Stream, Error>;
+}
+
+/// Number of commands waiting for the writer of a connection
+const SEND_BUFFER: usize = 16;
+
+/// Sends commands over a stream opened by a `Connector` and reopens it with an exponential
+/// backoff whenever it fails.
+///
+/// The received frames are decoded by the given codec, e.g. `LumenCacheCodec` for the replies
+/// to the own commands or `LumenCacheMonitorCodec` for the traffic of another controller.
+///
+/// Commands are handed to the writer of the current connection, so a stalled write never
+/// holds the transport while the connection is torn down.
+pub struct StreamTransport {
+    commands: Option<mpsc::Sender<Commands>>,
+}
+
+impl StreamTransport {
+    pub fn start<C, D>(
+        mut connector: C,
+        codec: D,
+        mut backoff: Backoff,
+        events: mpsc::Sender<TransportEvent>,
+    ) -> Arc<Mutex<StreamTransport>>
+    where
+        C: Connector,
+        D: Decoder<Error = Error> + Clone + Send + 'static,
+        D::Item: Into<TransportEvent> + Send,
+    {
+        let transport = Arc::new(Mutex::new(StreamTransport { commands: None }));
+        let connection = transport.clone();
+
+        tokio::spawn(async move {
+            loop {
+                match connector.connect().await {
+                    Ok(stream) => {
+                        log::info!("Connected to {}", connector.name());
+                        backoff.reset();
+
+                        let (reader, writer) = split(stream);
+                        let frames = FramedRead::new(reader, codec.clone());
+                        let sink = FramedWrite::new(writer, LumenCacheCodec);
+                        let (commands_tx, commands) = mpsc::channel(SEND_BUFFER);
+                        connection.lock().await.set_commands(Some(commands_tx));
+
+                        if events.send(TransportEvent::Connected).await.is_err() {
+                            break;
+                        }
+
+                        let result = select! {
+                            result = forward_frames(frames, &events) => result,
+                            result = forward_commands(commands, sink) => result,
+                        };
+
+                        if let Err(err) = result {
+                            log::warn!("Connection to {} failed: {}", connector.name(), err);
+                        }
+
+                        connection.lock().await.set_commands(None);
+
+                        if events.send(TransportEvent::Disconnected).await.is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        log::warn!("Could not connect to {}: {}", connector.name(), err);
+                    }
+                }
+
+                let delay = backoff.next_delay();
+                log::debug!("Reconnecting to {} in {:?}", connector.name(), delay);
+                sleep(delay).await;
+            }
+        });
+
+        transport
+    }
+
+    #[cfg(test)]
+    pub fn is_connected(&self) -> bool {
+        self.commands.is_some()
+    }
+
+    fn set_commands(&mut self, commands: Option<mpsc::Sender<Commands>>) {
+        self.commands = commands;
+    }
+}
+
+#[async_trait]
+impl Transport for StreamTransport {
+    async fn send(&mut self, command: Commands) -> Result<(), Error> {
+        log::trace!("Sending {:?}", command);
+
+        match &self.commands {
+            Some(commands) => commands
+                .try_send(command)
+                .map_err(|err| anyhow!("Failed to queue command: {}", err)),
+            None => Err(anyhow!("Not connected")),
+        }
+    }
+}
+
+/// Writes all commands of `commands` to `sink` until the sink fails
+async fn forward_commands<S>(
+    mut commands: mpsc::Receiver<Commands>,
+    mut sink: FramedWrite<WriteHalf<S>, LumenCacheCodec>,
+) -> Result<(), Error>
+where
+    S: AsyncWrite,
+{
+    while let Some(command) = commands.recv().await {
+        sink.send(command).await?;
+    }
+
+    Err(anyhow!("Command sender was dropped"))
+}
+
+/// Forwards all frames of `frames` to `events` until the stream fails or ends
+async fn forward_frames<S, D>(
+    mut frames: FramedRead<ReadHalf<S>, D>,
+    events: &mpsc::Sender<TransportEvent>,
+) -> Result<(), Error>
+where
+    S: AsyncRead,
+    D: Decoder<Error = Error>,
+    D::Item: Into<TransportEvent>,
+{
+    loop {
+        match frames.next().await {
+            Some(Ok(frame)) => {
+                events
+                    .send(frame.into())
+                    .await
+                    .map_err(|_| anyhow!("Event receiver was dropped"))?;
+            }
+            Some(Err(err)) => {
+                return Err(anyhow!("Failed to get frame: {}", err));
+            }
+            None => {
+                return Err(anyhow!("End of stream"));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codec::LumenCacheMonitorCodec;
+    use crate::protocol::decoder::{Response, Value};
+    use crate::protocol::encoder::{GetValueCommand, SetValueCommand};
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use tokio::time::Duration;
+
+    struct DuplexConnector {
+        streams: mpsc::Receiver<DuplexStream>,
+    }
+
+    #[async_trait]
+    impl Connector for DuplexConnector {
+        type Stream = DuplexStream;
+
+        fn name(&self) -> String {
+            String::from("duplex")
+        }
+
+        async fn connect(&mut self) -> Result<Self::Stream, Error> {
+            self.streams
+                .recv()
+                .await
+                .ok_or_else(|| anyhow!("No more streams"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_duplex_reconnect() {
+        let (streams_tx, streams) = mpsc::channel(1);
+        let (tx, mut rx) = mpsc::channel(10);
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(100));
+        let transport =
+            StreamTransport::start(DuplexConnector { streams }, LumenCacheCodec, backoff, tx);
+
+        let (client, mut bus) = duplex(64);
+        streams_tx.send(client).await.unwrap();
+        assert!(matches!(rx.recv().await, Some(TransportEvent::Connected)));
+        assert!(transport.lock().await.is_connected());
+
+        bus.write_all(b"(13,37)").await.unwrap();
+        assert!(matches!(
+            rx.recv().await,
+            Some(TransportEvent::Response(Response::Value(Value {
+                id: 13,
+                value: 37
+            })))
+        ));
+
+        drop(bus);
+        assert!(matches!(
+            rx.recv().await,
+            Some(TransportEvent::Disconnected)
+        ));
+        assert!(!transport.lock().await.is_connected());
+        assert!(transport
+            .lock()
+            .await
+            .send(Commands::GetValue(GetValueCommand { id: 13 }))
+            .await
+            .is_err());
+
+        let (client, mut bus) = duplex(64);
+        streams_tx.send(client).await.unwrap();
+        assert!(matches!(rx.recv().await, Some(TransportEvent::Connected)));
+        transport
+            .lock()
+            .await
+            .send(Commands::GetValue(GetValueCommand { id: 13 }))
+            .await
+            .unwrap();
+
+        let mut buf = [0; 8];
+        bus.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"[13,256]");
+    }
+
+    #[tokio::test]
+    async fn test_stalled_write() {
+        let (streams_tx, streams) = mpsc::channel(1);
+        let (tx, mut rx) = mpsc::channel(10);
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(100));
+        let transport =
+            StreamTransport::start(DuplexConnector { streams }, LumenCacheCodec, backoff, tx);
+
+        // The bus never reads, so the writer stalls after the first command
+        let (client, bus) = duplex(8);
+        streams_tx.send(client).await.unwrap();
+        assert!(matches!(rx.recv().await, Some(TransportEvent::Connected)));
+
+        for _ in 0..3 {
+            transport
+                .lock()
+                .await
+                .send(Commands::GetValue(GetValueCommand { id: 13 }))
+                .await
+                .unwrap();
+        }
+
+        // Sending never holds the transport, so the connection can still be torn down
+        drop(bus);
+        assert!(matches!(
+            rx.recv().await,
+            Some(TransportEvent::Disconnected)
+        ));
+        assert!(!transport.lock().await.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_monitor_codec() {
+        let (streams_tx, streams) = mpsc::channel(1);
+        let (tx, mut rx) = mpsc::channel(10);
+        let backoff = Backoff::new(Duration::from_millis(10), Duration::from_millis(100));
+        let _transport = StreamTransport::start(
+            DuplexConnector { streams },
+            LumenCacheMonitorCodec,
+            backoff,
+            tx,
+        );
+
+        let (client, mut bus) = duplex(64);
+        streams_tx.send(client).await.unwrap();
+        assert!(matches!(rx.recv().await, Some(TransportEvent::Connected)));
+
+        // Both the commands of the other controller and the replies become events
+        bus.write_all(b"[5,42](5,42)").await.unwrap();
+        assert!(matches!(
+            rx.recv().await,
+            Some(TransportEvent::Command(Commands::SetValue(
+                SetValueCommand { id: 5, value: 42 }
+            )))
+        ));
+        assert!(matches!(
+            rx.recv().await,
+            Some(TransportEvent::Response(Response::Value(Value {
+                id: 5,
+                value: 42
+            })))
+        ));
+    }
+}
@@ -0,0 +1,60 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::transport::stream::Connector;
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use tokio::net::TcpStream;
+
+/// Connects to a TCP gateway
+pub struct TcpConnector {
+    host: String,
+    port: u16,
+}
+
+impl TcpConnector {
+    pub fn new(host: String, port: u16) -> Self {
+        TcpConnector { host, port }
+    }
+}
+
+#[async_trait]
+impl Connector for TcpConnector {
+    type Stream = TcpStream;
+
+    fn name(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    async fn connect(&mut self) -> Result<Self::Stream, Error> {
+        Ok(TcpStream::connect((self.host.as_str(), self.port)).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_tcp_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut connector = TcpConnector::new(String::from("127.0.0.1"), port);
+        assert_eq!(connector.name(), format!("127.0.0.1:{}", port));
+
+        let mut stream = connector.connect().await.unwrap();
+        let (mut socket, _) = listener.accept().await.unwrap();
+        socket.write_all(b"(13,37)").await.unwrap();
+
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"(13,37)");
+
+        drop(listener);
+        assert!(connector.connect().await.is_err());
+    }
+}
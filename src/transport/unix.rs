@@ -0,0 +1,64 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::transport::stream::Connector;
+use anyhow::{Error, Result};
+use async_trait::async_trait;
+use tokio::net::UnixStream;
+
+/// Connects to a Unix socket exposed by a serial sharing daemon
+pub struct UnixConnector {
+    path: String,
+}
+
+impl UnixConnector {
+    pub fn new(path: String) -> Self {
+        UnixConnector { path }
+    }
+}
+
+#[async_trait]
+impl Connector for UnixConnector {
+    type Stream = UnixStream;
+
+    fn name(&self) -> String {
+        self.path.clone()
+    }
+
+    async fn connect(&mut self) -> Result<Self::Stream, Error> {
+        Ok(UnixStream::connect(&self.path).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::UnixListener;
+
+    #[tokio::test]
+    async fn test_unix_socket() {
+        let dir = std::env::temp_dir().join(format!("lumencache-unix-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("bus.sock");
+        let _ = fs::remove_file(&path);
+        let mut connector = UnixConnector::new(path.to_string_lossy().into_owned());
+
+        // The daemon did not create its socket yet
+        assert!(connector.connect().await.is_err());
+
+        let listener = UnixListener::bind(&path).unwrap();
+        let mut stream = connector.connect().await.unwrap();
+        let (mut socket, _) = listener.accept().await.unwrap();
+        socket.write_all(b"(13,37)").await.unwrap();
+
+        let mut buf = [0; 7];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"(13,37)");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
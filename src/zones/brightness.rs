@@ -38,7 +38,7 @@ impl PropertyStructure for BrightnessProperty {
             .minimum(0)
             .maximum(100)
             .multiple_of(1)
-            .read_only(false)
+            .read_only(self.controller.is_passive())
             .value(0_f64)
             .visible(true)
     }
@@ -55,7 +55,7 @@ impl Property for BuiltBrightnessProperty {
             .await;
 
         match receiver.await {
-            Ok(RequestResponse::Response(_)) => Ok(()),
+            Ok(RequestResponse::Response { .. }) => Ok(()),
             Ok(RequestResponse::Timeout) => Err(format!(
                 "Failed to set {} of {}: timeout",
                 self.property_handle.name, self.dm_id
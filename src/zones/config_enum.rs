@@ -0,0 +1,96 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::controller::Controller;
+use crate::protocol::encoder::ConfigField;
+use crate::zones::set_config;
+use async_trait::async_trait;
+use gateway_addon_rust::{property, property::Property, PropertyDescription, PropertyStructure};
+use std::fmt::Display;
+
+#[property]
+pub struct ConfigEnumProperty {
+    controller: Controller,
+    dm_id: u8,
+    field: ConfigField,
+    name: String,
+    title: String,
+    options: Vec<(u8, String)>,
+    value: String,
+}
+
+impl ConfigEnumProperty {
+    pub fn new(
+        controller: Controller,
+        dm_id: u8,
+        field: ConfigField,
+        name: &str,
+        title: &str,
+        options: Vec<(u8, String)>,
+        value: String,
+    ) -> Self {
+        ConfigEnumProperty {
+            controller,
+            dm_id,
+            field,
+            name: name.to_owned(),
+            title: title.to_owned(),
+            options,
+            value,
+        }
+    }
+}
+
+/// Lists `values` as options, followed by `current` if it is none of them
+pub fn options<T>(values: &[T], current: T) -> Vec<(u8, String)>
+where
+    T: Copy + Display + Into<u8> + PartialEq,
+{
+    let mut options: Vec<(u8, String)> = values
+        .iter()
+        .map(|value| ((*value).into(), value.to_string()))
+        .collect();
+
+    if !values.contains(&current) {
+        options.push((current.into(), current.to_string()));
+    }
+
+    options
+}
+
+impl PropertyStructure for ConfigEnumProperty {
+    type Value = String;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> PropertyDescription<Self::Value> {
+        PropertyDescription::default()
+            .title(self.title.clone())
+            .enum_(self.options.iter().map(|(_, name)| name.clone()).collect())
+            .read_only(self.controller.is_passive())
+            .value(self.value.clone())
+            .visible(true)
+    }
+}
+
+#[async_trait]
+impl Property for BuiltConfigEnumProperty {
+    async fn on_update(&mut self, value: Self::Value) -> Result<(), String> {
+        let dm_id = self.dm_id;
+        let field = self.field;
+        let name = self.property_handle.name.clone();
+
+        let raw = self
+            .options
+            .iter()
+            .find(|(_, name)| *name == value)
+            .map(|(raw, _)| *raw)
+            .ok_or_else(|| format!("Invalid {} {}", self.property_handle.name, value))?;
+
+        set_config(&mut self.controller, dm_id, field, &name, raw).await
+    }
+}
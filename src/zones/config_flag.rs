@@ -0,0 +1,74 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::controller::Controller;
+use crate::protocol::encoder::ConfigField;
+use crate::zones::set_config;
+use async_trait::async_trait;
+use gateway_addon_rust::{property, property::Property, PropertyDescription, PropertyStructure};
+
+#[property]
+pub struct ConfigFlagProperty {
+    controller: Controller,
+    dm_id: u8,
+    field: ConfigField,
+    name: String,
+    title: String,
+    value: u8,
+}
+
+impl ConfigFlagProperty {
+    pub fn new(
+        controller: Controller,
+        dm_id: u8,
+        field: ConfigField,
+        name: &str,
+        title: &str,
+        value: u8,
+    ) -> Self {
+        ConfigFlagProperty {
+            controller,
+            dm_id,
+            field,
+            name: name.to_owned(),
+            title: title.to_owned(),
+            value,
+        }
+    }
+}
+
+impl PropertyStructure for ConfigFlagProperty {
+    type Value = bool;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> PropertyDescription<Self::Value> {
+        PropertyDescription::default()
+            .title(self.title.clone())
+            .read_only(self.controller.is_passive())
+            .value(self.value > 0)
+            .visible(true)
+    }
+}
+
+#[async_trait]
+impl Property for BuiltConfigFlagProperty {
+    async fn on_update(&mut self, value: Self::Value) -> Result<(), String> {
+        let dm_id = self.dm_id;
+        let field = self.field;
+        let name = self.property_handle.name.clone();
+
+        set_config(
+            &mut self.controller,
+            dm_id,
+            field,
+            &name,
+            if value { 1 } else { 0 },
+        )
+        .await
+    }
+}
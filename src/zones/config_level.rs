@@ -0,0 +1,96 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::controller::Controller;
+use crate::protocol::encoder::ConfigField;
+use crate::zones::set_config;
+use async_trait::async_trait;
+use gateway_addon_rust::{property, property::Property, PropertyDescription, PropertyStructure};
+
+#[property]
+pub struct ConfigLevelProperty {
+    controller: Controller,
+    dm_id: u8,
+    field: ConfigField,
+    name: String,
+    title: String,
+    unit: Option<String>,
+    step: f64,
+    value: u8,
+}
+
+impl ConfigLevelProperty {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        controller: Controller,
+        dm_id: u8,
+        field: ConfigField,
+        name: &str,
+        title: &str,
+        unit: Option<&str>,
+        step: f64,
+        value: u8,
+    ) -> Self {
+        ConfigLevelProperty {
+            controller,
+            dm_id,
+            field,
+            name: name.to_owned(),
+            title: title.to_owned(),
+            unit: unit.map(|unit| unit.to_owned()),
+            step,
+            value,
+        }
+    }
+}
+
+pub fn level_to_value(level: u8, step: f64) -> f64 {
+    (level as f64 * step * 10_f64).round() / 10_f64
+}
+
+impl PropertyStructure for ConfigLevelProperty {
+    type Value = f64;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> PropertyDescription<Self::Value> {
+        let description = PropertyDescription::default()
+            .title(self.title.clone())
+            .minimum(0)
+            .maximum(level_to_value(u8::MAX, self.step))
+            .multiple_of(self.step)
+            .read_only(self.controller.is_passive())
+            .value(level_to_value(self.value, self.step))
+            .visible(true);
+
+        match &self.unit {
+            Some(unit) => description.unit(unit.clone()),
+            None => description,
+        }
+    }
+}
+
+#[async_trait]
+impl Property for BuiltConfigLevelProperty {
+    async fn on_update(&mut self, value: Self::Value) -> Result<(), String> {
+        let dm_id = self.dm_id;
+        let field = self.field;
+        let name = self.property_handle.name.clone();
+        let level = (value / self.step).round();
+
+        if !(0_f64..=u8::MAX as f64).contains(&level) {
+            return Err(format!(
+                "{} of {} must be between 0 and {}",
+                name,
+                dm_id,
+                level_to_value(u8::MAX, self.step)
+            ));
+        }
+
+        set_config(&mut self.controller, dm_id, field, &name, level as u8).await
+    }
+}
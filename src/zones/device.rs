@@ -4,12 +4,18 @@
  * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
 
 use crate::controller::Controller;
-use crate::protocol::decoder::Config;
+use crate::protocol::decoder::{Config, DimmingCurve, Mode, PwmFrequency};
+use crate::protocol::encoder::ConfigField;
 use crate::request::RequestResponse;
 use crate::zones::brightness::BrightnessProperty;
 use crate::zones::clear_scene::ClearSceneAction;
 use crate::zones::clear_scenes::ClearScenesAction;
+use crate::zones::config_enum::{options, ConfigEnumProperty};
+use crate::zones::config_flag::ConfigFlagProperty;
+use crate::zones::config_level::{level_to_value, ConfigLevelProperty};
+use crate::zones::info::InfoProperty;
 use crate::zones::on_off::OnOffProperty;
+use crate::zones::profile::{Capability, DeviceKind, DeviceProfile};
 use crate::zones::set_scene::SetSceneAction;
 use gateway_addon_rust::error::WebthingsError;
 use gateway_addon_rust::{
@@ -19,15 +25,32 @@ use gateway_addon_rust::{
 };
 use serde_json::json;
 
+const RAMP_DURATION_STEP: f64 = 0.1;
+
 #[device]
 pub struct LumenCacheDevice {
     config: Config,
+    profile: DeviceProfile,
     controller: Controller,
 }
 
 impl LumenCacheDevice {
     pub fn new(config: Config, controller: Controller) -> Self {
-        LumenCacheDevice { config, controller }
+        let profile = DeviceProfile::from_config(&config);
+
+        LumenCacheDevice {
+            config,
+            profile,
+            controller,
+        }
+    }
+}
+
+fn device_type(kind: DeviceKind) -> Option<DeviceType> {
+    match kind {
+        DeviceKind::Light => Some(DeviceType::Light),
+        DeviceKind::OnOffSwitch => Some(DeviceType::OnOffSwitch),
+        DeviceKind::Input => None,
     }
 }
 
@@ -37,22 +60,100 @@ impl DeviceStructure for LumenCacheDevice {
     }
 
     fn description(&self) -> DeviceDescription {
-        DeviceDescription::default()
-            .at_type(DeviceType::Light)
-            .title(format!("Light {}", self.config.id))
+        let description = match device_type(self.profile.kind) {
+            Some(at_type) => DeviceDescription::default().at_type(at_type),
+            None => DeviceDescription::default(),
+        };
+
+        description.title(self.profile.title(self.config.id))
     }
 
     fn properties(&self) -> Properties {
-        vec![
-            Box::new(OnOffProperty::new(self.controller.clone(), self.config.id)),
-            Box::new(BrightnessProperty::new(
-                self.controller.clone(),
-                self.config.id,
+        let controller = &self.controller;
+        let config = &self.config;
+        let profile = &self.profile;
+        let id = config.id;
+
+        let mut properties: Properties = Vec::new();
+
+        if profile.has(Capability::OnOff) {
+            properties.push(Box::new(OnOffProperty::new(controller.clone(), id)));
+        }
+
+        if profile.has(Capability::Brightness) {
+            properties.push(Box::new(BrightnessProperty::new(controller.clone(), id)));
+        }
+
+        properties.push(Box::new(ConfigEnumProperty::new(
+            controller.clone(),
+            id,
+            ConfigField::Mode,
+            "mode",
+            "Mode",
+            options(&Mode::ALL, config.mode),
+            config.mode.to_string(),
+        )));
+
+        if profile.has(Capability::Brightness) {
+            properties.extend(self.dimmer_properties());
+        }
+
+        let others: Properties = vec![
+            Box::new(ConfigFlagProperty::new(
+                controller.clone(),
+                id,
+                ConfigField::MotionSensorEnable,
+                "motionSensorEnable",
+                "Motion sensor enabled",
+                config.motion_sensor_enable,
             )),
-        ]
+            Box::new(ConfigFlagProperty::new(
+                controller.clone(),
+                id,
+                ConfigField::Mode6AlternateActions,
+                "mode6AlternateActions",
+                "Mode 6 alternate actions",
+                config.mode_6_alternate_actions,
+            )),
+            Box::new(ConfigFlagProperty::new(
+                controller.clone(),
+                id,
+                ConfigField::InvertedOutput,
+                "invertedOutput",
+                "Inverted output",
+                config.inverted_output,
+            )),
+            Box::new(InfoProperty::new(
+                "firmwareVersion",
+                "Firmware version",
+                config.firmware_version.clone(),
+            )),
+            Box::new(InfoProperty::new(
+                "hardwareType",
+                "Hardware type",
+                config.hardware_type.to_string(),
+            )),
+            Box::new(InfoProperty::new(
+                "hardwareVersion",
+                "Hardware version",
+                config.hardware_version.to_string(),
+            )),
+            Box::new(InfoProperty::new(
+                "serialNumber",
+                "Serial number",
+                config.hardware_serial_number.clone(),
+            )),
+        ];
+
+        properties.extend(others);
+        properties
     }
 
     fn actions(&self) -> Actions {
+        if self.controller.is_passive() || !self.profile.has(Capability::OnOff) {
+            return Vec::new();
+        }
+
         vec![
             Box::new(SetSceneAction::new(self.config.id, self.controller.clone())),
             Box::new(ClearSceneAction::new(
@@ -67,27 +168,151 @@ impl DeviceStructure for LumenCacheDevice {
     }
 }
 
+impl LumenCacheDevice {
+    fn dimmer_properties(&self) -> Properties {
+        let controller = &self.controller;
+        let config = &self.config;
+        let id = config.id;
+
+        vec![
+            Box::new(ConfigEnumProperty::new(
+                controller.clone(),
+                id,
+                ConfigField::DimmingCurve,
+                "dimmingCurve",
+                "Dimming curve",
+                options(&DimmingCurve::ALL, config.dimming_curve),
+                config.dimming_curve.to_string(),
+            )),
+            Box::new(ConfigEnumProperty::new(
+                controller.clone(),
+                id,
+                ConfigField::PwmFrequency,
+                "pwmFrequency",
+                "PWM frequency",
+                options(&PwmFrequency::ALL, config.pwm_frequency),
+                config.pwm_frequency.to_string(),
+            )),
+            Box::new(ConfigLevelProperty::new(
+                controller.clone(),
+                id,
+                ConfigField::MinimumOutputPwm,
+                "minimumOutputPwm",
+                "Minimum output PWM",
+                None,
+                1_f64,
+                config.minimum_output_pwm,
+            )),
+            Box::new(ConfigLevelProperty::new(
+                controller.clone(),
+                id,
+                ConfigField::MaximumOutputPwm,
+                "maximumOutputPwm",
+                "Maximum output PWM",
+                None,
+                1_f64,
+                config.maximum_output_pwm,
+            )),
+            Box::new(ConfigLevelProperty::new(
+                controller.clone(),
+                id,
+                ConfigField::ResumeLevel,
+                "resumeLevel",
+                "Resume level",
+                None,
+                1_f64,
+                config.resume_level,
+            )),
+            Box::new(ConfigLevelProperty::new(
+                controller.clone(),
+                id,
+                ConfigField::RampDuration,
+                "rampDuration",
+                "Default ramp duration",
+                Some("s"),
+                RAMP_DURATION_STEP,
+                config.ramp_duration,
+            )),
+        ]
+    }
+}
+
 impl BuiltLumenCacheDevice {
     pub async fn set_value(&mut self, value: u8) -> Result<(), WebthingsError> {
-        self.device_handle
-            .get_property("on")
-            .unwrap()
-            .lock()
-            .await
-            .property_handle_mut()
-            .set_value(Some(json!(value > 0)))
-            .await?;
-
-        self.device_handle
-            .get_property("brightness")
-            .unwrap()
-            .lock()
-            .await
-            .property_handle_mut()
-            .set_value(Some(
+        let values = vec![
+            ("on", json!(value > 0)),
+            (
+                "brightness",
                 json!((value as f64 / 255_f64 * 100_f64).round() as u8),
-            ))
-            .await
+            ),
+        ];
+
+        for (name, value) in values {
+            if let Some(property) = self.device_handle.get_property(name) {
+                property
+                    .lock()
+                    .await
+                    .property_handle_mut()
+                    .set_value(Some(value))
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn set_config(&mut self, config: Config) -> Result<(), WebthingsError> {
+        let values = vec![
+            ("mode", json!(config.mode.to_string())),
+            ("dimmingCurve", json!(config.dimming_curve.to_string())),
+            ("pwmFrequency", json!(config.pwm_frequency.to_string())),
+            ("minimumOutputPwm", json!(config.minimum_output_pwm)),
+            ("maximumOutputPwm", json!(config.maximum_output_pwm)),
+            ("resumeLevel", json!(config.resume_level)),
+            (
+                "rampDuration",
+                json!(level_to_value(config.ramp_duration, RAMP_DURATION_STEP)),
+            ),
+            ("motionSensorEnable", json!(config.motion_sensor_enable > 0)),
+            (
+                "mode6AlternateActions",
+                json!(config.mode_6_alternate_actions > 0),
+            ),
+            ("invertedOutput", json!(config.inverted_output > 0)),
+            ("firmwareVersion", json!(config.firmware_version)),
+            ("hardwareType", json!(config.hardware_type.to_string())),
+            (
+                "hardwareVersion",
+                json!(config.hardware_version.to_string()),
+            ),
+            ("serialNumber", json!(config.hardware_serial_number)),
+        ];
+
+        if DeviceProfile::from_config(&config) != self.profile {
+            log::info!(
+                "Profile of device {} changed, pair it again to update its capabilities",
+                config.id
+            );
+        }
+
+        self.config = config;
+
+        for (name, value) in values {
+            if let Some(property) = self.device_handle.get_property(name) {
+                property
+                    .lock()
+                    .await
+                    .property_handle_mut()
+                    .set_value(Some(value))
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn has_serial_number(&self, serial_number: &str) -> bool {
+        self.config.hardware_serial_number == serial_number
     }
 
     pub fn request_initial_values(&self) {
@@ -98,7 +323,7 @@ impl BuiltLumenCacheDevice {
             let receiver = controller.request_current_value(id).await;
 
             match receiver.await {
-                Ok(RequestResponse::Response(_)) => {}
+                Ok(RequestResponse::Response { .. }) => {}
                 Ok(RequestResponse::Timeout) => {
                     log::debug!("Failed to request initial value: timeout");
                 }
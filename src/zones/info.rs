@@ -0,0 +1,43 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use async_trait::async_trait;
+use gateway_addon_rust::{property, property::Property, PropertyDescription, PropertyStructure};
+
+#[property]
+pub struct InfoProperty {
+    name: String,
+    title: String,
+    value: String,
+}
+
+impl InfoProperty {
+    pub fn new(name: &str, title: &str, value: String) -> Self {
+        InfoProperty {
+            name: name.to_owned(),
+            title: title.to_owned(),
+            value,
+        }
+    }
+}
+
+impl PropertyStructure for InfoProperty {
+    type Value = String;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> PropertyDescription<Self::Value> {
+        PropertyDescription::default()
+            .title(self.title.clone())
+            .read_only(true)
+            .value(self.value.clone())
+            .visible(true)
+    }
+}
+
+#[async_trait]
+impl Property for BuiltInfoProperty {}
@@ -6,6 +6,34 @@
 pub mod brightness;
 pub mod clear_scene;
 pub mod clear_scenes;
+pub mod config_enum;
+pub mod config_flag;
+pub mod config_level;
 pub mod device;
+pub mod info;
 pub mod on_off;
+pub mod profile;
 pub mod set_scene;
+
+use crate::controller::Controller;
+use crate::protocol::encoder::ConfigField;
+use crate::request::RequestResponse;
+
+/// Writes a config field for a config property and waits until it was read back
+async fn set_config(
+    controller: &mut Controller,
+    dm_id: u8,
+    field: ConfigField,
+    name: &str,
+    value: u8,
+) -> Result<(), String> {
+    let receiver = controller.set_config(dm_id, field, value).await;
+
+    match receiver.await {
+        Ok(RequestResponse::Response { .. }) => Ok(()),
+        Ok(RequestResponse::Timeout) => {
+            Err(format!("Failed to set {} of {}: timeout", name, dm_id))
+        }
+        Err(err) => Err(format!("Failed to set {} of {}: {}", name, dm_id, err)),
+    }
+}
@@ -35,7 +35,7 @@ impl PropertyStructure for OnOffProperty {
         PropertyDescription::default()
             .at_type(PropertyType::OnOffProperty)
             .title("On")
-            .read_only(false)
+            .read_only(self.controller.is_passive())
             .value(false)
             .visible(true)
     }
@@ -52,7 +52,7 @@ impl Property for BuiltOnOffProperty {
             .await;
 
         match receiver.await {
-            Ok(RequestResponse::Response(_)) => Ok(()),
+            Ok(RequestResponse::Response { .. }) => Ok(()),
             Ok(RequestResponse::Timeout) => Err(format!(
                 "Failed to set {} of {}: timeout",
                 self.property_handle.name, self.dm_id
@@ -0,0 +1,148 @@
+/*
+ * This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at http://mozilla.org/MPL/2.0/. */
+
+use crate::protocol::decoder::{Config, HardwareType, Mode};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DeviceKind {
+    Light,
+    OnOffSwitch,
+    /// Input modules only report through scenes, so they have no Web Thing type
+    Input,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Capability {
+    OnOff,
+    Brightness,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DeviceProfile {
+    pub kind: DeviceKind,
+    pub capabilities: Vec<Capability>,
+}
+
+impl DeviceProfile {
+    pub fn from_config(config: &Config) -> Self {
+        // A motion sensor only switches the output, as the protocol has no motion report
+        let (kind, capabilities) = match (config.hardware_type, config.mode) {
+            (HardwareType::Input, _) | (_, Mode::Input) => (DeviceKind::Input, vec![]),
+            (HardwareType::Relay, _) | (_, Mode::Relay) | (_, Mode::Switch) => {
+                (DeviceKind::OnOffSwitch, vec![Capability::OnOff])
+            }
+            _ => (
+                DeviceKind::Light,
+                vec![Capability::OnOff, Capability::Brightness],
+            ),
+        };
+
+        DeviceProfile { kind, capabilities }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    pub fn title(&self, id: u8) -> String {
+        match self.kind {
+            DeviceKind::Light => format!("Light {}", id),
+            DeviceKind::OnOffSwitch => format!("Switch {}", id),
+            DeviceKind::Input => format!("Input {}", id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::decoder::{DimmingCurve, PwmFrequency};
+
+    fn config(hardware_type: HardwareType, mode: Mode, motion_sensor_enable: u8) -> Config {
+        Config {
+            id: 5,
+            hardware_type,
+            hardware_version: 3,
+            firmware_version: String::from("123456.12"),
+            hardware_serial_number: String::from("0123456789ABCDEF0123"),
+            mode,
+            dimming_curve: DimmingCurve::Linear,
+            pwm_frequency: PwmFrequency::Hz1000,
+            minimum_output_pwm: 0,
+            maximum_output_pwm: 255,
+            resume_level: 255,
+            ramp_duration: 0,
+            motion_sensor_enable,
+            mode_6_alternate_actions: 0,
+            inverted_output: 0,
+        }
+    }
+
+    #[test]
+    fn test_dimmer() {
+        let profile = DeviceProfile::from_config(&config(HardwareType::Dimmer, Mode::Dimmer, 0));
+        assert_eq!(profile.kind, DeviceKind::Light);
+        assert_eq!(
+            profile.capabilities,
+            vec![Capability::OnOff, Capability::Brightness]
+        );
+        assert_eq!(profile.title(5), "Light 5");
+    }
+
+    #[test]
+    fn test_unknown_hardware() {
+        let profile =
+            DeviceProfile::from_config(&config(HardwareType::Unknown(42), Mode::Unknown(42), 0));
+        assert_eq!(profile.kind, DeviceKind::Light);
+    }
+
+    #[test]
+    fn test_relay() {
+        let profile = DeviceProfile::from_config(&config(HardwareType::Relay, Mode::Dimmer, 0));
+        assert_eq!(profile.kind, DeviceKind::OnOffSwitch);
+        assert_eq!(profile.capabilities, vec![Capability::OnOff]);
+        assert!(!profile.has(Capability::Brightness));
+        assert_eq!(profile.title(5), "Switch 5");
+    }
+
+    #[test]
+    fn test_switch_mode() {
+        let profile = DeviceProfile::from_config(&config(HardwareType::LedDriver, Mode::Switch, 0));
+        assert_eq!(profile.kind, DeviceKind::OnOffSwitch);
+    }
+
+    #[test]
+    fn test_motion_sensor_enabled() {
+        // The protocol reports no motion events, so only the output is exposed
+        let profile = DeviceProfile::from_config(&config(HardwareType::Dimmer, Mode::Dimmer, 1));
+        assert_eq!(profile.kind, DeviceKind::Light);
+        assert_eq!(
+            profile.capabilities,
+            vec![Capability::OnOff, Capability::Brightness]
+        );
+    }
+
+    #[test]
+    fn test_motion_sensor_mode() {
+        let profile =
+            DeviceProfile::from_config(&config(HardwareType::Relay, Mode::MotionSensor, 0));
+        assert_eq!(profile.kind, DeviceKind::OnOffSwitch);
+        assert_eq!(profile.capabilities, vec![Capability::OnOff]);
+    }
+
+    #[test]
+    fn test_input() {
+        let profile = DeviceProfile::from_config(&config(HardwareType::Input, Mode::Dimmer, 1));
+        assert_eq!(profile.kind, DeviceKind::Input);
+        assert!(profile.capabilities.is_empty());
+        assert_eq!(profile.title(5), "Input 5");
+    }
+
+    #[test]
+    fn test_input_mode() {
+        let profile = DeviceProfile::from_config(&config(HardwareType::Dimmer, Mode::Input, 0));
+        assert_eq!(profile.kind, DeviceKind::Input);
+    }
+}